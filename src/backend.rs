@@ -0,0 +1,395 @@
+//! Pluggable output backends for simulated keyboard events.
+//!
+//! Every keyboard event produced by the functions in [`crate::typewriter`] is
+//! dispatched through a [`Backend`]. The default [`RdevBackend`] sends events
+//! to the operating system using `rdev::simulate`, but any other sink (a
+//! recorder, a remote machine, a virtual input device) can be installed with
+//! [`set_backend`]. The [`Recorder`] backend captures events against a
+//! virtual clock, which makes scripts testable without a display server.
+//!
+//! # Examples
+//!
+//! ```
+//! use rdev::{EventType, SimulateError};
+//! use typewriter::backend::{Backend, set_backend};
+//!
+//! struct Printer;
+//!
+//! impl Backend for Printer {
+//!     fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+//!         println!("{:?}", event_type);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! set_backend(Box::new(Printer));
+//! ```
+
+use rdev::{EventType, Key, SimulateError, simulate};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::{thread, time};
+
+/// A destination for simulated keyboard events.
+pub trait Backend {
+    /// Delivers a single event (such as a key press or release).
+    ///
+    /// # Errors
+    ///
+    /// Returns `SimulateError` if the event could not be delivered.
+    fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError>;
+
+    /// Waits for the specified number of milliseconds.
+    ///
+    /// The default implementation blocks the current thread. Backends that
+    /// do not drive a real device can override this to advance a virtual
+    /// clock instead.
+    fn sleep(&mut self, millis: u64) {
+        thread::sleep(time::Duration::from_millis(millis));
+    }
+
+    /// Returns the current time in milliseconds, counted from an arbitrary
+    /// starting point.
+    ///
+    /// The default implementation reads the system's monotonic clock.
+    /// Backends that override [`Backend::sleep`] should override this to
+    /// report their own clock.
+    fn now(&mut self) -> u64 {
+        static START: OnceLock<time::Instant> = OnceLock::new();
+        START.get_or_init(time::Instant::now).elapsed().as_millis() as u64
+    }
+
+    /// Returns the keys pressed through this backend that have not been
+    /// released yet, in press order.
+    ///
+    /// Chords leave these keys alone, so a key held with `down` stays held.
+    /// The default implementation does not track keys and returns none.
+    fn held_keys(&self) -> Vec<Key> {
+        Vec::new()
+    }
+}
+
+/// The default backend, which sends events to the operating system.
+///
+/// Keys pressed through this backend are tracked process-wide, since the
+/// operating system's keyboard is shared by every thread. This lets
+/// [`RdevBackend::shutdown`] release them from any thread before the process
+/// exits, so the user's keyboard is not left with a key stuck down.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RdevBackend;
+
+/// Keys pressed on the operating system that have not been released yet.
+static PRESSED: Mutex<Vec<Key>> = Mutex::new(Vec::new());
+
+/// Set once [`RdevBackend::shutdown`] has run; later events are discarded.
+static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    /// Set while the current thread holds the `PRESSED` lock in
+    /// [`RdevBackend::send`].
+    static SENDING: Cell<bool> = const { Cell::new(false) };
+}
+
+fn pressed() -> MutexGuard<'static, Vec<Key>> {
+    // A panic while the lock was held must not stop keys from being released
+    PRESSED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Releases every key in `pressed` and discards any events sent afterwards.
+fn release_all(pressed: &mut Vec<Key>) {
+    SHUT_DOWN.store(true, Ordering::SeqCst);
+    for key in pressed.drain(..).rev() {
+        if simulate(&EventType::KeyRelease(key)).is_err() {
+            eprintln!("We could not release {:?}", key);
+        }
+    }
+}
+
+/// The `PRESSED` lock, held by [`RdevBackend::send`] while an event is in
+/// flight.
+struct Sending(MutexGuard<'static, Vec<Key>>);
+
+impl Sending {
+    fn lock() -> Self {
+        let guard = pressed();
+        SENDING.set(true);
+        Self(guard)
+    }
+}
+
+impl Drop for Sending {
+    fn drop(&mut self) {
+        SENDING.set(false);
+        // `shutdown` cannot take the lock from a panic hook on this thread,
+        // so the keys are released here as the panic unwinds
+        if thread::panicking() {
+            release_all(&mut self.0);
+        }
+    }
+}
+
+impl RdevBackend {
+    /// Releases every key still pressed on the operating system and discards
+    /// any events sent afterwards.
+    ///
+    /// This is safe to call from any thread and more than once. It should be
+    /// called on every path that terminates a running script (interrupts,
+    /// signals, errors and panics) before the process exits. If the current
+    /// thread panicked while sending an event, the keys are released once
+    /// the panic unwinds out of [`RdevBackend::send`] instead.
+    pub fn shutdown() {
+        if SENDING.get() {
+            return;
+        }
+        release_all(&mut pressed());
+    }
+}
+
+impl Backend for RdevBackend {
+    fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        // Holding the lock while simulating keeps `shutdown` from racing
+        // with a press that is in flight on another thread
+        let mut sending = Sending::lock();
+        if SHUT_DOWN.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        simulate(event_type)?;
+        track(&mut sending.0, event_type);
+        Ok(())
+    }
+
+    fn held_keys(&self) -> Vec<Key> {
+        pressed().clone()
+    }
+}
+
+/// Updates a list of held keys, in press order, after `event_type` is sent.
+fn track(held: &mut Vec<Key>, event_type: &EventType) {
+    match event_type {
+        EventType::KeyPress(key) if !held.contains(key) => held.push(*key),
+        EventType::KeyRelease(key) => held.retain(|k| k != key),
+        _ => {}
+    }
+}
+
+/// An event captured by a [`Recorder`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecordedEvent {
+    /// Virtual time in milliseconds at which the event was sent.
+    pub time: u64,
+    /// The event that was sent.
+    pub event_type: EventType,
+}
+
+#[derive(Debug, Default)]
+struct Recording {
+    clock: u64,
+    events: Vec<RecordedEvent>,
+    held: Vec<Key>,
+}
+
+/// A backend that records events instead of sending them.
+///
+/// Sleeping advances a virtual clock rather than blocking, so recorded
+/// scripts run instantly and produce deterministic timestamps. Clones share
+/// the same recording, which lets a handle be kept while another clone is
+/// installed as the active backend.
+///
+/// # Examples
+///
+/// ```
+/// use rdev::{EventType, Key};
+/// use typewriter::backend::Recorder;
+/// use typewriter::config::Config;
+/// use typewriter::typewriter::type_chord;
+///
+/// let recorder = Recorder::new();
+/// recorder.record(|| type_chord(vec![Key::ControlLeft, Key::KeyC], &Config::default()));
+///
+/// let events: Vec<_> = recorder.events().iter().map(|e| e.event_type).collect();
+/// assert_eq!(
+///     events,
+///     vec![
+///         EventType::KeyPress(Key::ControlLeft),
+///         EventType::KeyPress(Key::KeyC),
+///         EventType::KeyRelease(Key::ControlLeft),
+///         EventType::KeyRelease(Key::KeyC),
+///     ]
+/// );
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Recorder {
+    recording: Rc<RefCell<Recording>>,
+}
+
+impl Recorder {
+    /// Creates an empty recorder with its clock at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Installs this recorder on the current thread while `f` runs, see
+    /// [`install`].
+    pub fn record<R>(&self, f: impl FnOnce() -> R) -> R {
+        install(Box::new(self.clone()), f)
+    }
+
+    /// Returns every event recorded so far.
+    pub fn events(&self) -> Vec<RecordedEvent> {
+        self.recording.borrow().events.clone()
+    }
+
+    /// Returns the current virtual time in milliseconds.
+    pub fn elapsed(&self) -> u64 {
+        self.recording.borrow().clock
+    }
+
+    /// Discards all recorded events and resets the clock to zero.
+    pub fn clear(&self) {
+        *self.recording.borrow_mut() = Recording::default();
+    }
+
+    /// Discards the events recorded so far, but keeps the clock and the
+    /// held keys, so recording can carry on where it left off.
+    pub fn discard_events(&self) {
+        self.recording.borrow_mut().events.clear();
+    }
+}
+
+impl Backend for Recorder {
+    fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        let mut recording = self.recording.borrow_mut();
+        let time = recording.clock;
+        recording.events.push(RecordedEvent {
+            time,
+            event_type: *event_type,
+        });
+        track(&mut recording.held, event_type);
+        Ok(())
+    }
+
+    fn sleep(&mut self, millis: u64) {
+        let mut recording = self.recording.borrow_mut();
+        recording.clock = recording.clock.saturating_add(millis);
+    }
+
+    fn now(&mut self) -> u64 {
+        self.recording.borrow().clock
+    }
+
+    fn held_keys(&self) -> Vec<Key> {
+        self.recording.borrow().held.clone()
+    }
+}
+
+thread_local! {
+    static BACKEND: RefCell<Box<dyn Backend>> = RefCell::new(Box::new(RdevBackend));
+}
+
+/// Installs the backend used by the current thread and returns the previous one.
+///
+/// Backends are tracked per thread, so a script running on a worker thread
+/// uses whatever backend was installed on that thread. Threads start out
+/// using [`RdevBackend`].
+///
+/// # Arguments
+///
+/// * `backend` - The backend that should receive all subsequent events
+pub fn set_backend(backend: Box<dyn Backend>) -> Box<dyn Backend> {
+    BACKEND.with(|cell| cell.replace(backend))
+}
+
+/// Installs `backend` on the current thread while `f` runs.
+///
+/// The previously installed backend is restored afterwards, even if `f`
+/// panics.
+pub fn install<R>(backend: Box<dyn Backend>, f: impl FnOnce() -> R) -> R {
+    /// Puts the previous backend back when dropped.
+    struct Restore(Option<Box<dyn Backend>>);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            if let Some(previous) = self.0.take() {
+                set_backend(previous);
+            }
+        }
+    }
+
+    let _restore = Restore(Some(set_backend(backend)));
+    f()
+}
+
+/// Runs `f` with the backend installed on the current thread.
+pub(crate) fn with_backend<R>(f: impl FnOnce(&mut dyn Backend) -> R) -> R {
+    BACKEND.with(|cell| f(cell.borrow_mut().as_mut()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::typewriter::{held_keys, press_chord, release_chord, sleep};
+    use std::panic::{AssertUnwindSafe, catch_unwind};
+
+    #[test]
+    fn recorder_timestamps_events_with_a_virtual_clock() {
+        let recorder = Recorder::new();
+        let config = Config::default();
+        recorder.record(|| {
+            sleep(1_000);
+            press_chord(vec![Key::KeyA], &config);
+        });
+        assert_eq!(
+            recorder.events(),
+            [RecordedEvent {
+                time: 1_000,
+                event_type: EventType::KeyPress(Key::KeyA),
+            }]
+        );
+        assert_eq!(recorder.elapsed(), 1_000 + config.key_delay);
+    }
+
+    #[test]
+    fn recorder_clock_saturates() {
+        let recorder = Recorder::new();
+        recorder.record(|| {
+            sleep(u64::MAX);
+            sleep(1);
+        });
+        assert_eq!(recorder.elapsed(), u64::MAX);
+    }
+
+    #[test]
+    fn install_restores_the_previous_backend_after_a_panic() {
+        let outer = Recorder::new();
+        let inner = Recorder::new();
+        outer.record(|| {
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                inner.record(|| {
+                    sleep(10);
+                    panic!("script failed");
+                })
+            }));
+            assert!(result.is_err());
+            sleep(20);
+        });
+        assert_eq!(inner.elapsed(), 10);
+        assert_eq!(outer.elapsed(), 20);
+    }
+
+    #[test]
+    fn recorder_tracks_held_keys() {
+        let recorder = Recorder::new();
+        let config = Config::default();
+        recorder.record(|| {
+            press_chord(vec![Key::ShiftLeft, Key::KeyA], &config);
+            assert_eq!(held_keys(), [Key::ShiftLeft, Key::KeyA]);
+            release_chord(vec![Key::ShiftLeft], &config);
+            assert_eq!(held_keys(), [Key::KeyA]);
+        });
+    }
+}
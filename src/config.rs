@@ -0,0 +1,401 @@
+//! Runtime configuration for the typewriter functions.
+//!
+//! A [`Config`] is passed to every function that turns text into keystrokes.
+//! Scripts start from the configuration given on the command line and can
+//! change it as they run with `set` directives.
+
+use crate::humanize::{Humanize, Rng, Typos};
+use crate::layout::Layout;
+use rdev::Key;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Implements `by_name` and `Display` for an enum of options from a single
+/// list of its variants and their names, with `$what` describing an option
+/// in error messages.
+macro_rules! named_variants {
+    ($type:ident, $what:literal, { $($variant:ident => $name:literal),+ $(,)? }) => {
+        impl $type {
+            /// Looks up a variant by name, ignoring case.
+            ///
+            /// # Errors
+            ///
+            /// Returns a message listing the valid names if `name` is not one of them.
+            pub fn by_name(name: &str) -> Result<Self, String> {
+                $(
+                    if name.eq_ignore_ascii_case($name) {
+                        return Ok(Self::$variant);
+                    }
+                )+
+                Err(format!(
+                    "Unknown {} {:?} (expected one of: {})",
+                    $what,
+                    name,
+                    [$($name),+].join(", ")
+                ))
+            }
+        }
+
+        impl ::std::fmt::Display for $type {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                let name = match self {
+                    $(Self::$variant => $name),+
+                };
+                write!(f, "{}", name)
+            }
+        }
+    };
+}
+pub(crate) use named_variants;
+
+/// What to do with characters the keyboard layout cannot produce.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Unsupported {
+    /// Refuse to run a script containing the character.
+    #[default]
+    Error,
+    /// Skip the character and print a warning.
+    Warn,
+    /// Skip the character silently.
+    Skip,
+    /// Type a close substitute instead, such as `e` for `é`, or `?` if
+    /// there is none.
+    Fallback,
+}
+
+named_variants!(Unsupported, "policy", {
+    Error => "error",
+    Warn => "warn",
+    Skip => "skip",
+    Fallback => "fallback",
+});
+
+/// How to type characters that the keyboard layout cannot produce, by
+/// entering their code point through the operating system's input method.
+///
+/// # Examples
+///
+/// ```
+/// use typewriter::editor::Editor;
+/// use typewriter::parser::run_script;
+///
+/// let editor = Editor::new();
+/// editor.record(|| run_script("set unicode = \"ibus\"; \"Zoë\";")).unwrap();
+/// assert_eq!(editor.text(), "Zoë");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnicodeInput {
+    /// Only type characters that are on the layout.
+    #[default]
+    None,
+    /// `Ctrl+Shift+U`, the hex code point, then `Space`. Works in GTK
+    /// applications and anywhere IBus is the input method on Linux.
+    Ibus,
+    /// The hex code point (as UTF-16) while holding Option. Requires the
+    /// "Unicode Hex Input" input source on macOS.
+    MacOs,
+    /// `+` on the keypad and the hex code point while holding Alt. Requires
+    /// the `EnableHexNumpad` registry setting on Windows, and only reaches
+    /// the Basic Multilingual Plane.
+    Windows,
+}
+
+impl UnicodeInput {
+    /// Returns true if `c` can be entered with this input method, given the
+    /// keys `layout` needs to type the hex code.
+    pub fn can_type(self, c: char, layout: &Layout) -> bool {
+        let on_layout = |chars: &str| chars.chars().all(|c| layout.chord(c).is_some());
+        if c.is_control() || !on_layout("0123456789abcdef") {
+            return false;
+        }
+        match self {
+            UnicodeInput::None => false,
+            UnicodeInput::Ibus => on_layout("u"),
+            UnicodeInput::MacOs => true,
+            UnicodeInput::Windows => c.len_utf16() == 1,
+        }
+    }
+}
+
+named_variants!(UnicodeInput, "input method", {
+    None => "none",
+    Ibus => "ibus",
+    MacOs => "macos",
+    Windows => "windows",
+});
+
+/// The chord typed for a newline (`\n`) in a string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Newline {
+    /// Return.
+    #[default]
+    Return,
+    /// Shift+Return, which starts a new line in chat applications where
+    /// Return sends the message.
+    ShiftReturn,
+}
+
+impl Newline {
+    /// Returns the keys to press together.
+    pub fn chord(self) -> Vec<Key> {
+        match self {
+            Newline::Return => vec![Key::Return],
+            Newline::ShiftReturn => vec![Key::ShiftLeft, Key::Return],
+        }
+    }
+}
+
+named_variants!(Newline, "newline", {
+    Return => "return",
+    ShiftReturn => "shift+return",
+});
+
+/// What to type for a carriage return (`\r`) in a string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CarriageReturn {
+    /// Nothing, so that Windows line endings (`\r\n`) start a single new line.
+    #[default]
+    Ignore,
+    /// The same chord as a newline.
+    Newline,
+}
+
+named_variants!(CarriageReturn, "carriage return", {
+    Ignore => "ignore",
+    Newline => "newline",
+});
+
+/// How leading indentation in multi-line strings is typed, to compensate for
+/// editors that indent new lines automatically.
+///
+/// # Examples
+///
+/// ```
+/// use typewriter::editor::Editor;
+/// use typewriter::parser::run_script;
+///
+/// let editor = Editor::new();
+/// editor.set_auto_indent(true);
+/// editor
+///     .record(|| run_script("set indent = \"relative\"; \"if x {\n    y();\n}\";"))
+///     .unwrap();
+/// assert_eq!(editor.text(), "if x {\n    y();\n}");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Indent {
+    /// Type indentation as written.
+    #[default]
+    Keep,
+    /// Skip indentation after each newline and let the editor indent.
+    Strip,
+    /// Assume the editor repeats the previous line's indentation, and only
+    /// type the difference: extra indentation is typed and missing
+    /// indentation is deleted with Backspace.
+    Relative,
+}
+
+named_variants!(Indent, "indent", {
+    Keep => "keep",
+    Strip => "strip",
+    Relative => "relative",
+});
+
+/// How fast text is typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    /// A fixed number of milliseconds to wait after each character.
+    Millis(u64),
+    /// Words per minute, counting five characters as a word.
+    Wpm(u64),
+}
+
+impl Default for Speed {
+    fn default() -> Self {
+        Speed::Millis(50)
+    }
+}
+
+impl Speed {
+    /// Returns how many milliseconds to wait after each character, given
+    /// the delay after each key event.
+    ///
+    /// For [`Speed::Wpm`], the time spent pressing and releasing a key is
+    /// subtracted, so that a character without modifiers takes
+    /// 60000 / (5 × wpm) milliseconds in total.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use typewriter::config::Speed;
+    /// assert_eq!(Speed::Millis(100).char_delay(20), 100);
+    /// assert_eq!(Speed::Wpm(60).char_delay(20), 200 - 40);
+    /// assert_eq!(Speed::Wpm(u64::MAX).char_delay(20), 0);
+    /// ```
+    pub fn char_delay(self, key_delay: u64) -> u64 {
+        match self {
+            Speed::Millis(millis) => millis,
+            Speed::Wpm(wpm) => {
+                (60_000 / wpm.max(1).saturating_mul(5)).saturating_sub(key_delay.saturating_mul(2))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Speed::Millis(millis) => write!(f, "{}ms per char", millis),
+            Speed::Wpm(wpm) => write!(f, "{}wpm", wpm),
+        }
+    }
+}
+
+/// Values for the `${name}` references in a script's strings.
+///
+/// Values given on the command line take precedence over `let` bindings in
+/// the script, so a script can bind defaults that are replaced for each run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variables {
+    bound: HashMap<String, String>,
+    overrides: HashMap<String, String>,
+}
+
+impl Variables {
+    /// Returns true if `name` can be used as a variable name: a letter or
+    /// underscore followed by letters, digits and underscores.
+    pub fn is_valid_name(name: &str) -> bool {
+        let mut chars = name.chars();
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }
+
+    /// Binds `name` to `value`, as `let` does in a script.
+    pub fn bind(&mut self, name: &str, value: String) {
+        self.bound.insert(name.to_string(), value);
+    }
+
+    /// Sets `name` to `value`, taking precedence over any binding.
+    pub fn set_override(&mut self, name: &str, value: String) {
+        self.overrides.insert(name.to_string(), value);
+    }
+
+    /// Returns the value of `name`, or `None` if it is not defined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use typewriter::config::Variables;
+    /// let mut variables = Variables::default();
+    /// variables.bind("user", "alice".to_string());
+    /// variables.set_override("user", "bob".to_string());
+    /// assert_eq!(variables.get("user"), Some("bob"));
+    /// ```
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.overrides
+            .get(name)
+            .or_else(|| self.bound.get(name))
+            .map(String::as_str)
+    }
+}
+
+/// Settings that control how text is typed.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Keyboard layout used to translate characters into key chords.
+    pub layout: Layout,
+    /// Input method used for characters that `layout` cannot produce.
+    pub unicode: UnicodeInput,
+    /// What to do with characters that neither `layout` nor `unicode` can produce.
+    pub unsupported: Unsupported,
+    /// The chord typed for `\n`.
+    pub newline: Newline,
+    /// What to type for `\r`.
+    pub carriage_return: CarriageReturn,
+    /// How indentation after newlines is typed.
+    pub indent: Indent,
+    /// How fast strings without a speed of their own are typed.
+    pub speed: Speed,
+    /// Milliseconds to wait after each key press or release, so the
+    /// operating system has time to process it.
+    pub key_delay: u64,
+    /// Random variation in the delay between characters.
+    pub humanize: Humanize,
+    /// Simulated typos that are corrected straight away.
+    pub typos: Typos,
+    /// The random number generator used for humanizing and typos.
+    pub rng: Rng,
+    /// Values for `${name}` references in strings.
+    pub variables: Variables,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            layout: Layout::default(),
+            unicode: UnicodeInput::default(),
+            unsupported: Unsupported::default(),
+            newline: Newline::default(),
+            carriage_return: CarriageReturn::default(),
+            indent: Indent::default(),
+            speed: Speed::default(),
+            key_delay: 20,
+            humanize: Humanize::default(),
+            typos: Typos::default(),
+            rng: Rng::default(),
+            variables: Variables::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Returns true if `c` can be typed, either with the layout or through
+    /// the Unicode input method.
+    pub fn can_type(&self, c: char) -> bool {
+        matches!(c, '\n' | '\r')
+            || self.layout.chord(c).is_some()
+            || self.unicode.can_type(c, &self.layout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn option_names_round_trip() {
+        for policy in [
+            Unsupported::Error,
+            Unsupported::Warn,
+            Unsupported::Skip,
+            Unsupported::Fallback,
+        ] {
+            assert_eq!(Unsupported::by_name(&policy.to_string()), Ok(policy));
+        }
+        assert_eq!(Newline::ShiftReturn.to_string(), "shift+return");
+        assert_eq!(UnicodeInput::by_name("MacOS"), Ok(UnicodeInput::MacOs));
+        assert_eq!(
+            Indent::by_name("tabs"),
+            Err("Unknown indent \"tabs\" (expected one of: keep, strip, relative)".to_string())
+        );
+    }
+
+    #[test]
+    fn words_per_minute_never_overflow() {
+        assert_eq!(Speed::Wpm(0).char_delay(0), 12_000);
+        assert_eq!(Speed::Wpm(u64::MAX).char_delay(u64::MAX), 0);
+        assert_eq!(Speed::Millis(30).char_delay(u64::MAX), 30);
+    }
+
+    #[test]
+    fn overrides_take_precedence_over_bindings() {
+        let mut variables = Variables::default();
+        variables.bind("user", "ada".to_string());
+        variables.set_override("user", "grace".to_string());
+        variables.bind("host", "example.com".to_string());
+        assert_eq!(variables.get("user"), Some("grace"));
+        assert_eq!(variables.get("host"), Some("example.com"));
+        assert_eq!(variables.get("missing"), None);
+    }
+}
@@ -0,0 +1,460 @@
+//! A simulated text editor that shows what a script would actually type.
+//!
+//! The [`Editor`] backend interprets the stream of key presses coming out of
+//! the [`typewriter`](crate::typewriter) functions the way a plain text field
+//! would: printable keys insert the character their [`Layout`] assigns them
+//! (honoring Shift, AltGr and dead keys), and editing keys such as Backspace,
+//! Delete, Return, Tab, the arrow keys, Home and End move the cursor or modify
+//! the buffer. Code points entered through any of the
+//! [`UnicodeInput`](crate::config::UnicodeInput) methods are inserted as
+//! characters. Other chords involving Control, Alt or Meta are treated as
+//! shortcuts and ignored.
+//!
+//! A grave, acute, circumflex, tilde or diaeresis dead key followed by a
+//! vowel (or `n` and `y` where they take the accent) inserts the accented
+//! letter, and followed by Space inserts the accent itself. Other pairs,
+//! including dead keys loaded from keymap files such as `dead_caron`, insert
+//! both characters.
+//!
+//! With [`Editor::set_auto_indent`], Return also repeats the indentation of
+//! the current line, like a code editor would.
+//!
+//! # Examples
+//!
+//! ```
+//! use typewriter::editor::Editor;
+//! use typewriter::parser::run_script;
+//!
+//! let editor = Editor::new();
+//! editor.record(|| run_script("\"Hello!\"; [Left]; [Backspace]; \",\";")).unwrap();
+//! assert_eq!(editor.text(), "Hell,!");
+//! ```
+
+use crate::backend::{Backend, install};
+use crate::layout::{self, KEYPAD_DIGITS, Layout};
+use rdev::{EventType, Key, SimulateError};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+#[derive(Debug, Default)]
+struct Buffer {
+    text: Vec<char>,
+    cursor: usize,
+    /// Keys pressed and not yet released, in press order.
+    held: Vec<Key>,
+    layout: Layout,
+    /// A dead key waiting to be combined with the next key press.
+    dead: Option<char>,
+    /// The hex digits of a code point being entered through a Unicode input method.
+    compose: Option<(Compose, String)>,
+    /// Whether Return repeats the current line's indentation on the new line.
+    auto_indent: bool,
+    /// Virtual time in milliseconds, advanced by sleeping.
+    clock: u64,
+}
+
+/// The Unicode input method a code point is being entered with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compose {
+    /// `Ctrl+Shift+U`, hex digits, then `Space` or `Return`.
+    Ibus,
+    /// Hex digits of UTF-16 code units while holding Option.
+    MacOs,
+    /// `+` on the keypad and hex digits while holding Alt.
+    Windows,
+}
+
+impl Buffer {
+    fn is_held(&self, key: Key) -> bool {
+        self.held.contains(&key)
+    }
+
+    /// The shift level (1 to 4) selected by the held modifiers.
+    fn level(&self) -> usize {
+        let shift = self.is_held(Key::ShiftLeft) || self.is_held(Key::ShiftRight);
+        layout::level(shift, self.is_held(Key::AltGr))
+    }
+
+    fn shortcut(&self) -> bool {
+        [
+            Key::ControlLeft,
+            Key::ControlRight,
+            Key::Alt,
+            Key::MetaLeft,
+            Key::MetaRight,
+        ]
+        .iter()
+        .any(|key| self.is_held(*key))
+    }
+
+    /// Index of the first character on the line containing `pos`.
+    fn line_start(&self, pos: usize) -> usize {
+        self.text[..pos]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |i| i + 1)
+    }
+
+    /// Index of the newline (or end of buffer) terminating the line containing `pos`.
+    fn line_end(&self, pos: usize) -> usize {
+        self.text[pos..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map_or(self.text.len(), |i| pos + i)
+    }
+
+    fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    /// Starts a new line, indenting it like the current one if auto-indent is on.
+    fn newline(&mut self) {
+        if !self.auto_indent {
+            self.insert('\n');
+            return;
+        }
+        let start = self.line_start(self.cursor);
+        let indent: Vec<char> = self.text[start..self.cursor]
+            .iter()
+            .take_while(|c| matches!(c, ' ' | '\t'))
+            .copied()
+            .collect();
+        // Like most editors, drop the indentation left on a line that has nothing else
+        if start + indent.len() == self.line_end(self.cursor) {
+            self.text.drain(start..start + indent.len());
+            self.cursor = start;
+        }
+        self.insert('\n');
+        for c in indent {
+            self.insert(c);
+        }
+    }
+
+    /// The hex digit typed by `key`, if any.
+    fn hex_digit(&self, key: Key) -> Option<char> {
+        match KEYPAD_DIGITS.iter().position(|k| *k == key) {
+            Some(digit) => char::from_digit(digit as u32, 10),
+            None => self
+                .layout
+                .char_at(key, self.level())
+                .filter(char::is_ascii_hexdigit),
+        }
+    }
+
+    /// Handles key presses that enter a code point through a Unicode input
+    /// method, returning true if `key` was consumed.
+    fn compose(&mut self, key: Key) -> bool {
+        let control = self.is_held(Key::ControlLeft) || self.is_held(Key::ControlRight);
+        let shift = self.is_held(Key::ShiftLeft) || self.is_held(Key::ShiftRight);
+        let alt = self.is_held(Key::Alt);
+        let digit = self.hex_digit(key);
+        match self.compose.take() {
+            Some((Compose::Ibus, hex)) if matches!(key, Key::Space | Key::Return) => {
+                self.commit(Compose::Ibus, &hex);
+            }
+            Some((Compose::Ibus, _)) if key == Key::Escape => {}
+            Some((method, mut hex)) => {
+                hex.extend(digit);
+                self.compose = Some((method, hex));
+            }
+            None if control && shift && self.layout.char_at(key, 1) == Some('u') => {
+                self.compose = Some((Compose::Ibus, String::new()));
+            }
+            None if alt && !control && key == Key::KpPlus => {
+                self.compose = Some((Compose::Windows, String::new()));
+            }
+            None if alt && !control && digit.is_some() => {
+                self.compose = Some((Compose::MacOs, digit.into_iter().collect()));
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Inserts the character whose code was entered with `method`, if valid.
+    fn commit(&mut self, method: Compose, hex: &str) {
+        let chars: Vec<char> = match method {
+            Compose::Ibus | Compose::Windows => u32::from_str_radix(hex, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .into_iter()
+                .collect(),
+            Compose::MacOs if hex.len().is_multiple_of(4) => {
+                let units = (0..hex.len())
+                    .step_by(4)
+                    .filter_map(|i| u16::from_str_radix(&hex[i..i + 4], 16).ok());
+                char::decode_utf16(units).filter_map(Result::ok).collect()
+            }
+            Compose::MacOs => Vec::new(),
+        };
+        for c in chars {
+            self.insert(c);
+        }
+    }
+
+    fn press(&mut self, key: Key) {
+        if !self.held.contains(&key) {
+            self.held.push(key);
+        }
+        if is_modifier(key) {
+            return;
+        }
+        if self.compose(key) || self.shortcut() {
+            return;
+        }
+
+        match key {
+            Key::Backspace => {
+                if self.cursor > 0 {
+                    self.cursor -= 1;
+                    self.text.remove(self.cursor);
+                }
+            }
+            Key::Delete => {
+                if self.cursor < self.text.len() {
+                    self.text.remove(self.cursor);
+                }
+            }
+            Key::Return => self.newline(),
+            Key::Tab => self.insert('\t'),
+            Key::LeftArrow => self.cursor = self.cursor.saturating_sub(1),
+            Key::RightArrow => self.cursor = (self.cursor + 1).min(self.text.len()),
+            Key::Home => self.cursor = self.line_start(self.cursor),
+            Key::End => self.cursor = self.line_end(self.cursor),
+            Key::UpArrow => {
+                let start = self.line_start(self.cursor);
+                if start == 0 {
+                    self.cursor = 0;
+                } else {
+                    let column = self.cursor - start;
+                    let previous = self.line_start(start - 1);
+                    self.cursor = (previous + column).min(start - 1);
+                }
+            }
+            Key::DownArrow => {
+                let end = self.line_end(self.cursor);
+                if end == self.text.len() {
+                    self.cursor = end;
+                } else {
+                    let column = self.cursor - self.line_start(self.cursor);
+                    let next = end + 1;
+                    self.cursor = (next + column).min(self.line_end(next));
+                }
+            }
+            _ => {
+                let Some(c) = self.layout.char_at(key, self.level()) else {
+                    return;
+                };
+                match self.dead.take() {
+                    // A dead key followed by Space produces the dead character itself
+                    Some(dead) if key == Key::Space => self.insert(dead),
+                    Some(dead) => match accent(dead, c) {
+                        Some(accented) => self.insert(accented),
+                        None => {
+                            self.insert(dead);
+                            self.insert(c);
+                        }
+                    },
+                    None if self.layout.is_dead(c) => self.dead = Some(c),
+                    None => self.insert(c),
+                }
+            }
+        }
+    }
+
+    fn release(&mut self, key: Key) {
+        self.held.retain(|k| *k != key);
+        let alt_compose = matches!(self.compose, Some((Compose::MacOs | Compose::Windows, _)));
+        if key == Key::Alt
+            && alt_compose
+            && let Some((method, hex)) = self.compose.take()
+        {
+            self.commit(method, &hex);
+        }
+    }
+}
+
+/// Dead keys that can be combined with a following letter, and the letters
+/// they produce from each base letter.
+const ACCENTS: [(char, &str, &str); 5] = [
+    ('`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ('´', "aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+    ('^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    ('~', "anoANO", "ãñõÃÑÕ"),
+    ('¨', "aeiouyAEIOUY", "äëïöüÿÄËÏÖÜŸ"),
+];
+
+/// The letter produced by following the `dead` key with `base`, if the
+/// two combine.
+fn accent(dead: char, base: char) -> Option<char> {
+    let (_, bases, accented) = ACCENTS.iter().find(|(d, _, _)| *d == dead)?;
+    let index = bases.chars().position(|c| c == base)?;
+    accented.chars().nth(index)
+}
+
+/// A backend that applies key presses to an in-memory text buffer.
+///
+/// [`Editor::text`] returns what was typed while the editor was installed
+/// with [`Editor::record`]. Like the [`Recorder`](crate::backend::Recorder),
+/// sleeping advances a virtual clock instead of blocking.
+#[derive(Debug, Default, Clone)]
+pub struct Editor {
+    buffer: Rc<RefCell<Buffer>>,
+}
+
+impl Editor {
+    /// Creates an empty US-QWERTY editor with the cursor at the start of the buffer.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty editor that interprets keys using `layout`.
+    pub fn with_layout(layout: Layout) -> Self {
+        let editor = Self::default();
+        editor.buffer.borrow_mut().layout = layout;
+        editor
+    }
+
+    /// Makes Return indent the new line like the current one, as code
+    /// editors do.
+    pub fn set_auto_indent(&self, enabled: bool) {
+        self.buffer.borrow_mut().auto_indent = enabled;
+    }
+
+    /// Installs this editor on the current thread while `f` runs, see
+    /// [`install`].
+    pub fn record<R>(&self, f: impl FnOnce() -> R) -> R {
+        install(Box::new(self.clone()), f)
+    }
+
+    /// Returns the current contents of the buffer.
+    pub fn text(&self) -> String {
+        self.buffer.borrow().text.iter().collect()
+    }
+
+    /// Returns the cursor position as a character index into [`Editor::text`].
+    pub fn cursor(&self) -> usize {
+        self.buffer.borrow().cursor
+    }
+}
+
+impl Backend for Editor {
+    fn send(&mut self, event_type: &EventType) -> Result<(), SimulateError> {
+        let mut buffer = self.buffer.borrow_mut();
+        match event_type {
+            EventType::KeyPress(key) => buffer.press(*key),
+            EventType::KeyRelease(key) => buffer.release(*key),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn sleep(&mut self, millis: u64) {
+        let mut buffer = self.buffer.borrow_mut();
+        buffer.clock = buffer.clock.saturating_add(millis);
+    }
+
+    fn now(&mut self) -> u64 {
+        self.buffer.borrow().clock
+    }
+
+    fn held_keys(&self) -> Vec<Key> {
+        self.buffer.borrow().held.clone()
+    }
+}
+
+fn is_modifier(key: Key) -> bool {
+    matches!(
+        key,
+        Key::ShiftLeft
+            | Key::ShiftRight
+            | Key::ControlLeft
+            | Key::ControlRight
+            | Key::Alt
+            | Key::AltGr
+            | Key::MetaLeft
+            | Key::MetaRight
+    )
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::parser::run_script;
+    use std::fmt;
+
+    /// Runs `f` in a new editor and returns the resulting text.
+    pub(crate) fn typed<T, E: fmt::Debug>(f: impl FnOnce() -> Result<T, E>) -> String {
+        let editor = Editor::new();
+        editor.record(f).unwrap();
+        editor.text()
+    }
+
+    #[test]
+    fn types_dead_keys_and_alt_gr() {
+        let editor = Editor::with_layout(Layout::de());
+        let script = "set layout = \"de\"; \"^1 @z\"; [BackQuote]; [E]; [BackQuote]; [X];";
+        editor.record(|| run_script(script)).unwrap();
+        assert_eq!(editor.text(), "^1 @zê^x");
+    }
+
+    #[test]
+    fn applies_editing_keys() {
+        assert_eq!(
+            typed(|| run_script("\"ab\nd\"; [Up]; [End]; \"c\"; [Home]; [Delete];")),
+            "bc\nd"
+        );
+        assert_eq!(
+            typed(|| run_script("\"abc\"; [Left]; [Left]; [Backspace]; [Tab];")),
+            "\tbc"
+        );
+    }
+
+    #[test]
+    fn ignores_shortcuts() {
+        assert_eq!(
+            typed(|| run_script("\"a\"; [Ctrl, A]; [Alt, Tab]; \"b\";")),
+            "ab"
+        );
+    }
+
+    #[test]
+    fn clock_saturates() {
+        let mut editor = Editor::new();
+        editor
+            .record(|| run_script("18446744073709551615; 1;"))
+            .unwrap();
+        assert_eq!(editor.now(), u64::MAX);
+    }
+
+    #[test]
+    fn enters_code_points() {
+        for method in ["ibus", "macos", "windows"] {
+            let script = format!("set unicode = \"{}\"; \"→é\";", method);
+            assert_eq!(typed(|| run_script(&script)), "→é", "with {}", method);
+        }
+        assert_eq!(
+            typed(|| run_script("set unicode = \"macos\"; \"😀\";")),
+            "😀"
+        );
+    }
+
+    #[test]
+    fn repeats_indentation_when_auto_indenting() {
+        let editor = Editor::new();
+        editor.set_auto_indent(true);
+        editor.record(|| run_script("\"  a\nb\n\nc\";")).unwrap();
+        assert_eq!(editor.text(), "  a\n  b\n\n  c");
+    }
+
+    #[test]
+    fn relative_indentation_matches_auto_indent() {
+        let code = "fn main() {\n    if x {\n        y();\n    }\n}";
+        let editor = Editor::new();
+        editor.set_auto_indent(true);
+        let script = format!("set indent = \"relative\"; {:?};", code);
+        editor.record(|| run_script(&script)).unwrap();
+        assert_eq!(editor.text(), code);
+    }
+}
@@ -0,0 +1,260 @@
+//! Human-like variation in typing speed.
+//!
+//! By default every character is followed by the same fixed delay. With
+//! [`Humanize`] the delay varies randomly around that value, following a
+//! uniform or normal [`Distribution`], and typing pauses a little longer after
+//! punctuation and whitespace, the way people do between words and sentences.
+//!
+//! [`Typos`] go one step further and occasionally hit a key next to the
+//! intended one, then pause, press Backspace and type the right character, so
+//! the text that ends up on screen is still exactly what the script says.
+//!
+//! Randomness comes from a small seedable [`Rng`], so a run can be reproduced
+//! exactly by fixing the seed with `set seed = 42;` or `--seed 42`.
+//!
+//! # Examples
+//!
+//! ```
+//! use typewriter::backend::Recorder;
+//! use typewriter::parser::run_script;
+//!
+//! let script = "set humanize = \"normal\"; set seed = 7; \"Hello, world.\";";
+//! let first = Recorder::new();
+//! first.record(|| run_script(script)).unwrap();
+//! let second = Recorder::new();
+//! second.record(|| run_script(script)).unwrap();
+//! assert_eq!(first.events(), second.events());
+//! ```
+
+use crate::config::named_variants;
+use crate::layout::Layout;
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A small, seedable pseudo-random number generator (SplitMix64).
+///
+/// The generator is not suitable for cryptography. Clones continue from the
+/// same state independently.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: Cell<u64>,
+}
+
+impl Rng {
+    /// Creates a generator that always produces the same sequence for `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        Self {
+            state: Cell::new(seed),
+        }
+    }
+
+    /// Returns the next 64 random bits.
+    pub fn next_u64(&self) -> u64 {
+        let state = self.state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+        self.state.set(state);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number uniformly distributed in `[0, 1)`.
+    pub fn next_f64(&self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl Default for Rng {
+    /// Creates a generator seeded from the current time.
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        Self::seeded(nanos)
+    }
+}
+
+/// The shape of the random variation added to each delay.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Distribution {
+    /// No variation; every delay is exact.
+    #[default]
+    Off,
+    /// Evenly spread within `jitter` milliseconds either side.
+    Uniform,
+    /// Normally distributed with a standard deviation of `jitter` milliseconds.
+    Normal,
+}
+
+named_variants!(Distribution, "distribution", {
+    Off => "off",
+    Uniform => "uniform",
+    Normal => "normal",
+});
+
+/// Settings for human-like typing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Humanize {
+    /// How delays vary. Humanizing is disabled when this is `Off`.
+    pub distribution: Distribution,
+    /// The spread of the variation in milliseconds.
+    pub jitter: u64,
+    /// Extra milliseconds to pause after `.`, `,`, `!`, `?`, `;` and `:`.
+    pub punctuation_pause: u64,
+    /// Extra milliseconds to pause after whitespace.
+    pub whitespace_pause: u64,
+}
+
+impl Default for Humanize {
+    fn default() -> Self {
+        Self {
+            distribution: Distribution::Off,
+            jitter: 30,
+            punctuation_pause: 200,
+            whitespace_pause: 40,
+        }
+    }
+}
+
+impl Humanize {
+    /// Returns how long to wait after typing `c`, given the configured delay
+    /// between characters.
+    ///
+    /// The result is exactly `millis` when humanizing is off, and never
+    /// negative otherwise.
+    pub fn delay(&self, c: char, millis: u64, rng: &Rng) -> u64 {
+        let pause = if matches!(c, '.' | ',' | '!' | '?' | ';' | ':') {
+            self.punctuation_pause
+        } else if c.is_whitespace() {
+            self.whitespace_pause
+        } else {
+            0
+        };
+        let jitter = self.jitter as f64;
+        let offset = match self.distribution {
+            Distribution::Off => return millis,
+            Distribution::Uniform => (rng.next_f64() * 2.0 - 1.0) * jitter,
+            Distribution::Normal => {
+                // Box-Muller transform; 1 - u keeps the logarithm finite
+                let u = 1.0 - rng.next_f64();
+                let v = rng.next_f64();
+                (-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos() * jitter
+            }
+        };
+        (millis.saturating_add(pause) as f64 + offset)
+            .round()
+            .max(0.0) as u64
+    }
+}
+
+/// Settings for simulated typos and their corrections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Typos {
+    /// The chance, in percent, of hitting a neighboring key before the
+    /// intended one. Typos are disabled when this is zero.
+    pub rate: u64,
+    /// Milliseconds to pause after a typo before correcting it.
+    pub pause: u64,
+}
+
+impl Default for Typos {
+    fn default() -> Self {
+        Self {
+            rate: 0,
+            pause: 300,
+        }
+    }
+}
+
+impl Typos {
+    /// Decides whether to mistype `c`, returning the wrong character to type
+    /// (and correct) before it.
+    ///
+    /// The wrong character is typed by a key physically next to the one for
+    /// `c` on `layout`, see [`Layout::neighbors`].
+    pub fn typo(&self, c: char, layout: &Layout, rng: &Rng) -> Option<char> {
+        if self.rate == 0 || rng.next_f64() * 100.0 >= self.rate as f64 {
+            return None;
+        }
+        let neighbors = layout.neighbors(c);
+        if neighbors.is_empty() {
+            return None;
+        }
+        Some(neighbors[(rng.next_u64() % neighbors.len() as u64) as usize])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Recorder;
+    use crate::editor::Editor;
+    use crate::parser::run_script;
+    use rdev::{EventType, Key};
+
+    #[test]
+    fn delay_is_exact_when_off() {
+        let humanize = Humanize::default();
+        let rng = Rng::seeded(1);
+        assert_eq!(humanize.delay('.', 50, &rng), 50);
+        assert_eq!(humanize.delay(' ', 50, &rng), 50);
+    }
+
+    #[test]
+    fn delay_stays_within_jitter_and_pauses() {
+        let humanize = Humanize {
+            distribution: Distribution::Uniform,
+            ..Humanize::default()
+        };
+        let rng = Rng::seeded(2);
+        for _ in 0..1_000 {
+            assert!((20..=80).contains(&humanize.delay('a', 50, &rng)));
+            assert!((220..=280).contains(&humanize.delay('.', 50, &rng)));
+            assert!((60..=120).contains(&humanize.delay(' ', 50, &rng)));
+        }
+    }
+
+    #[test]
+    fn delay_saturates_instead_of_overflowing() {
+        let humanize = Humanize {
+            distribution: Distribution::Uniform,
+            jitter: 0,
+            ..Humanize::default()
+        };
+        assert_eq!(humanize.delay('.', u64::MAX, &Rng::seeded(3)), u64::MAX);
+    }
+
+    #[test]
+    fn typos_leave_exactly_the_literal_text() {
+        let text = "The quick brown fox jumps over the lazy dog, twice!\nThen it rests.";
+        for seed in 0..20 {
+            let script = format!("set typo_rate = 30; set seed = {}; {:?};", seed, text);
+            let editor = Editor::new();
+            editor.record(|| run_script(&script)).unwrap();
+            assert_eq!(editor.text(), text, "with seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn typos_are_corrected_with_backspace() {
+        let recorder = Recorder::new();
+        recorder
+            .record(|| run_script("set typo_rate = 100; set seed = 3; \"g\";"))
+            .unwrap();
+        let presses: Vec<Key> = recorder
+            .events()
+            .iter()
+            .filter_map(|event| match event.event_type {
+                EventType::KeyPress(key) => Some(key),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(presses.len(), 3);
+        assert!(
+            Layout::us()
+                .neighbors('g')
+                .contains(&Layout::us().char_at(presses[0], 1).unwrap())
+        );
+        assert_eq!(presses[1..], [Key::Backspace, Key::KeyG]);
+    }
+}
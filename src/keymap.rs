@@ -0,0 +1,747 @@
+//! Loading keyboard layouts from keymap files.
+//!
+//! Two formats are supported:
+//!
+//! * XKB keymaps, as printed by `xkbcomp -xkb $DISPLAY keymap.xkb`. The keys
+//!   of the first `xkb_symbols` section are read, using the first four shift
+//!   levels of group 1. `include` statements are not followed, so use a
+//!   compiled keymap rather than a file from `/usr/share/X11/xkb/symbols`.
+//! * A TOML table mapping each character to the keys that type it, with
+//!   optional `name` and `dead` entries:
+//!
+//! ```toml
+//! name = "de-custom"
+//! dead = ["^"]
+//!
+//! "z" = ["KeyY"]
+//! "Z" = ["ShiftLeft", "KeyY"]
+//! "@" = ["AltGr", "KeyQ"]
+//! "^" = ["BackQuote"]
+//! ```
+//!
+//! Key names are the same as in scripts. Files ending in `.toml` are read as
+//! TOML tables and everything else as XKB keymaps.
+//!
+//! # Examples
+//!
+//! ```
+//! use rdev::Key;
+//! use typewriter::keymap::from_xkb;
+//!
+//! let layout = from_xkb(
+//!     "custom",
+//!     r#"xkb_symbols "basic" {
+//!         key <AD01> { [ q, Q, at ] };
+//!         key <AC11> { [ adiaeresis, Adiaeresis ] };
+//!     };"#,
+//! )
+//! .unwrap();
+//! assert_eq!(layout.chord('@'), Some(&[vec![Key::AltGr, Key::KeyQ]][..]));
+//! assert_eq!(layout.chord('Ä'), Some(&[vec![Key::ShiftLeft, Key::Quote]][..]));
+//! ```
+
+use crate::layout::Layout;
+use crate::parser::parse_key;
+use rdev::Key;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use toml::Spanned;
+
+/// An error encountered while loading a keymap file.
+#[derive(Debug)]
+pub enum KeymapError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file is malformed, with the 1-based line the problem is on.
+    Syntax { line: usize, message: String },
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Io(err) => write!(f, "{}", err),
+            KeymapError::Syntax { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl std::error::Error for KeymapError {}
+
+impl From<io::Error> for KeymapError {
+    fn from(err: io::Error) -> Self {
+        KeymapError::Io(err)
+    }
+}
+
+fn syntax_error(line: usize, message: impl Into<String>) -> KeymapError {
+    KeymapError::Syntax {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Loads a layout from an XKB keymap or, if the file name ends in `.toml`,
+/// a TOML table. The layout is named after the file.
+///
+/// # Errors
+///
+/// Returns `KeymapError` if the file cannot be read or parsed.
+pub fn load(path: &Path) -> Result<Layout, KeymapError> {
+    let source = fs::read_to_string(path)?;
+    let name = path.file_stem().map_or_else(
+        || path.display().to_string(),
+        |s| s.to_string_lossy().into_owned(),
+    );
+    match path.extension() {
+        Some(ext) if ext.eq_ignore_ascii_case("toml") => from_toml(&name, &source),
+        _ => from_xkb(&name, &source),
+    }
+}
+
+/// XKB names of the physical keys that produce characters.
+const XKB_KEYS: &[(&str, Key)] = &[
+    ("TLDE", Key::BackQuote),
+    ("AE01", Key::Num1),
+    ("AE02", Key::Num2),
+    ("AE03", Key::Num3),
+    ("AE04", Key::Num4),
+    ("AE05", Key::Num5),
+    ("AE06", Key::Num6),
+    ("AE07", Key::Num7),
+    ("AE08", Key::Num8),
+    ("AE09", Key::Num9),
+    ("AE10", Key::Num0),
+    ("AE11", Key::Minus),
+    ("AE12", Key::Equal),
+    ("AD01", Key::KeyQ),
+    ("AD02", Key::KeyW),
+    ("AD03", Key::KeyE),
+    ("AD04", Key::KeyR),
+    ("AD05", Key::KeyT),
+    ("AD06", Key::KeyY),
+    ("AD07", Key::KeyU),
+    ("AD08", Key::KeyI),
+    ("AD09", Key::KeyO),
+    ("AD10", Key::KeyP),
+    ("AD11", Key::LeftBracket),
+    ("AD12", Key::RightBracket),
+    ("BKSL", Key::BackSlash),
+    ("AC12", Key::BackSlash),
+    ("AC01", Key::KeyA),
+    ("AC02", Key::KeyS),
+    ("AC03", Key::KeyD),
+    ("AC04", Key::KeyF),
+    ("AC05", Key::KeyG),
+    ("AC06", Key::KeyH),
+    ("AC07", Key::KeyJ),
+    ("AC08", Key::KeyK),
+    ("AC09", Key::KeyL),
+    ("AC10", Key::SemiColon),
+    ("AC11", Key::Quote),
+    ("LSGT", Key::IntlBackslash),
+    ("AB01", Key::KeyZ),
+    ("AB02", Key::KeyX),
+    ("AB03", Key::KeyC),
+    ("AB04", Key::KeyV),
+    ("AB05", Key::KeyB),
+    ("AB06", Key::KeyN),
+    ("AB07", Key::KeyM),
+    ("AB08", Key::Comma),
+    ("AB09", Key::Dot),
+    ("AB10", Key::Slash),
+    ("SPCE", Key::Space),
+];
+
+/// Keysym names for the printable ASCII characters from `!` (0x21) to `~`
+/// (0x7e). Letters and digits are named after themselves.
+const ASCII_KEYSYMS: &[&str] = &[
+    "exclam",
+    "quotedbl",
+    "numbersign",
+    "dollar",
+    "percent",
+    "ampersand",
+    "apostrophe",
+    "parenleft",
+    "parenright",
+    "asterisk",
+    "plus",
+    "comma",
+    "minus",
+    "period",
+    "slash",
+    "0",
+    "1",
+    "2",
+    "3",
+    "4",
+    "5",
+    "6",
+    "7",
+    "8",
+    "9",
+    "colon",
+    "semicolon",
+    "less",
+    "equal",
+    "greater",
+    "question",
+    "at",
+    "A",
+    "B",
+    "C",
+    "D",
+    "E",
+    "F",
+    "G",
+    "H",
+    "I",
+    "J",
+    "K",
+    "L",
+    "M",
+    "N",
+    "O",
+    "P",
+    "Q",
+    "R",
+    "S",
+    "T",
+    "U",
+    "V",
+    "W",
+    "X",
+    "Y",
+    "Z",
+    "bracketleft",
+    "backslash",
+    "bracketright",
+    "asciicircum",
+    "underscore",
+    "grave",
+    "a",
+    "b",
+    "c",
+    "d",
+    "e",
+    "f",
+    "g",
+    "h",
+    "i",
+    "j",
+    "k",
+    "l",
+    "m",
+    "n",
+    "o",
+    "p",
+    "q",
+    "r",
+    "s",
+    "t",
+    "u",
+    "v",
+    "w",
+    "x",
+    "y",
+    "z",
+    "braceleft",
+    "bar",
+    "braceright",
+    "asciitilde",
+];
+
+/// Keysym names for the Latin-1 characters from U+00A0 to U+00FF.
+const LATIN1_KEYSYMS: &[&str] = &[
+    "nobreakspace",
+    "exclamdown",
+    "cent",
+    "sterling",
+    "currency",
+    "yen",
+    "brokenbar",
+    "section",
+    "diaeresis",
+    "copyright",
+    "ordfeminine",
+    "guillemotleft",
+    "notsign",
+    "hyphen",
+    "registered",
+    "macron",
+    "degree",
+    "plusminus",
+    "twosuperior",
+    "threesuperior",
+    "acute",
+    "mu",
+    "paragraph",
+    "periodcentered",
+    "cedilla",
+    "onesuperior",
+    "masculine",
+    "guillemotright",
+    "onequarter",
+    "onehalf",
+    "threequarters",
+    "questiondown",
+    "Agrave",
+    "Aacute",
+    "Acircumflex",
+    "Atilde",
+    "Adiaeresis",
+    "Aring",
+    "AE",
+    "Ccedilla",
+    "Egrave",
+    "Eacute",
+    "Ecircumflex",
+    "Ediaeresis",
+    "Igrave",
+    "Iacute",
+    "Icircumflex",
+    "Idiaeresis",
+    "ETH",
+    "Ntilde",
+    "Ograve",
+    "Oacute",
+    "Ocircumflex",
+    "Otilde",
+    "Odiaeresis",
+    "multiply",
+    "Oslash",
+    "Ugrave",
+    "Uacute",
+    "Ucircumflex",
+    "Udiaeresis",
+    "Yacute",
+    "THORN",
+    "ssharp",
+    "agrave",
+    "aacute",
+    "acircumflex",
+    "atilde",
+    "adiaeresis",
+    "aring",
+    "ae",
+    "ccedilla",
+    "egrave",
+    "eacute",
+    "ecircumflex",
+    "ediaeresis",
+    "igrave",
+    "iacute",
+    "icircumflex",
+    "idiaeresis",
+    "eth",
+    "ntilde",
+    "ograve",
+    "oacute",
+    "ocircumflex",
+    "otilde",
+    "odiaeresis",
+    "division",
+    "oslash",
+    "ugrave",
+    "uacute",
+    "ucircumflex",
+    "udiaeresis",
+    "yacute",
+    "thorn",
+    "ydiaeresis",
+];
+
+/// Other named keysyms found on common layouts, including alternative
+/// spellings of Latin-1 names.
+const EXTRA_KEYSYMS: &[(&str, char)] = &[
+    ("space", ' '),
+    ("guillemetleft", '«'),
+    ("guillemetright", '»'),
+    ("ordmasculine", 'º'),
+    ("Ooblique", 'Ø'),
+    ("ooblique", 'ø'),
+    ("EuroSign", '€'),
+    ("endash", '–'),
+    ("emdash", '—'),
+    ("ellipsis", '…'),
+    ("leftsinglequotemark", '‘'),
+    ("rightsinglequotemark", '’'),
+    ("singlelowquotemark", '‚'),
+    ("leftdoublequotemark", '“'),
+    ("rightdoublequotemark", '”'),
+    ("doublelowquotemark", '„'),
+    ("dagger", '†'),
+    ("doubledagger", '‡'),
+    ("trademark", '™'),
+    ("leftarrow", '←'),
+    ("uparrow", '↑'),
+    ("rightarrow", '→'),
+    ("downarrow", '↓'),
+    ("oe", 'œ'),
+    ("OE", 'Œ'),
+];
+
+/// Dead keysyms and the character each one types when followed by Space.
+const DEAD_KEYSYMS: &[(&str, char)] = &[
+    ("dead_grave", '`'),
+    ("dead_acute", '´'),
+    ("dead_circumflex", '^'),
+    ("dead_tilde", '~'),
+    ("dead_macron", '¯'),
+    ("dead_breve", '˘'),
+    ("dead_abovedot", '˙'),
+    ("dead_diaeresis", '¨'),
+    ("dead_abovering", '°'),
+    ("dead_doubleacute", '˝'),
+    ("dead_caron", 'ˇ'),
+    ("dead_cedilla", '¸'),
+    ("dead_ogonek", '˛'),
+];
+
+/// The character a keysym types, and whether it is a dead key.
+///
+/// Keysyms that do not type a character (such as `Shift_L` or `NoSymbol`)
+/// return `None`.
+fn keysym_char(keysym: &str) -> Option<(char, bool)> {
+    let mut chars = keysym.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some((c, false));
+    }
+    if let Some(&(_, c)) = DEAD_KEYSYMS.iter().find(|(name, _)| *name == keysym) {
+        return Some((c, true));
+    }
+    // Names such as Ugrave also start with U, so only all-hex suffixes are code points
+    let unicode = keysym
+        .strip_prefix('U')
+        .filter(|hex| !hex.is_empty() && hex.chars().all(|c| c.is_ascii_hexdigit()));
+    let code = if let Some(hex) = unicode {
+        u32::from_str_radix(hex, 16).ok()?
+    } else if let Some(hex) = keysym.strip_prefix("0x") {
+        // Unicode keysyms are offset by 0x1000000; Latin-1 keysyms equal their code point
+        match u32::from_str_radix(hex, 16).ok()? {
+            code @ 0x1000000.. => code - 0x1000000,
+            code @ (0x20..=0x7e | 0xa0..=0xff) => code,
+            _ => return None,
+        }
+    } else if let Some(i) = ASCII_KEYSYMS.iter().position(|name| *name == keysym) {
+        0x21 + i as u32
+    } else if let Some(i) = LATIN1_KEYSYMS.iter().position(|name| *name == keysym) {
+        0xa0 + i as u32
+    } else {
+        return EXTRA_KEYSYMS
+            .iter()
+            .find(|(name, _)| *name == keysym)
+            .map(|&(_, c)| (c, false));
+    };
+    char::from_u32(code).map(|c| (c, false))
+}
+
+/// Returns the 1-based line number of the byte offset `pos`.
+fn line_at(source: &str, pos: usize) -> usize {
+    source[..pos].matches('\n').count() + 1
+}
+
+/// Returns the offset of the `}` matching the `{` at `open`.
+fn matching_brace(source: &str, open: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in source[open..].char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Replaces `//` comments with spaces, keeping byte offsets intact.
+fn blank_comments(source: &str) -> String {
+    source
+        .split('\n')
+        .map(|line| match line.find("//") {
+            Some(i) => format!("{}{}", &line[..i], " ".repeat(line.len() - i)),
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the keysyms of group 1 in the body of a `key <NAME> { ... }` block.
+fn group1_keysyms(block: &str) -> Option<Vec<&str>> {
+    let list_start = match block.find("symbols[") {
+        Some(i) => {
+            let rest = &block[i..];
+            let group = rest.find(']')?;
+            if !rest[8..group].eq_ignore_ascii_case("group1") {
+                return None;
+            }
+            i + group + 1 + rest[group + 1..].find('[')?
+        }
+        None => block.find('[')?,
+    };
+    let list_end = list_start + block[list_start..].find(']')?;
+    Some(
+        block[list_start + 1..list_end]
+            .split(',')
+            .map(str::trim)
+            .collect(),
+    )
+}
+
+/// Builds a layout from the `xkb_symbols` section of an XKB keymap.
+///
+/// # Errors
+///
+/// Returns `KeymapError::Syntax` if there is no `xkb_symbols` section or a
+/// brace is not closed.
+pub fn from_xkb(name: &str, source: &str) -> Result<Layout, KeymapError> {
+    let source = blank_comments(source);
+    let section = source
+        .find("xkb_symbols")
+        .ok_or_else(|| syntax_error(1, "No xkb_symbols section found"))?;
+    let open = section
+        + source[section..].find('{').ok_or_else(|| {
+            syntax_error(line_at(&source, section), "Expected { after xkb_symbols")
+        })?;
+    let close = matching_brace(&source, open)
+        .ok_or_else(|| syntax_error(line_at(&source, open), "Unclosed xkb_symbols section"))?;
+
+    // Characters by level, so that characters reachable with fewer
+    // modifiers are preferred over the same character on a higher level
+    let mut levels: [Vec<(char, Key, bool)>; 4] = Default::default();
+    let mut pos = open + 1;
+    while let Some(i) = source[pos..close].find("key") {
+        let start = pos + i;
+        pos = start + 3;
+        let preceded_by_word = source[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphanumeric() || c == '_' || c == '.');
+        let rest = source[pos..close].trim_start();
+        if preceded_by_word || !rest.starts_with('<') {
+            continue;
+        }
+        let name_start = close - rest.len() + 1;
+        let name_end = name_start
+            + source[name_start..close].find('>').ok_or_else(|| {
+                syntax_error(line_at(&source, start), "Expected > after key name")
+            })?;
+        let block_open = name_end
+            + source[name_end..close].find('{').ok_or_else(|| {
+                syntax_error(line_at(&source, start), "Expected { after key name")
+            })?;
+        let block_close = matching_brace(&source[..close], block_open)
+            .ok_or_else(|| syntax_error(line_at(&source, block_open), "Unclosed key definition"))?;
+        pos = block_close + 1;
+
+        let key_name = &source[name_start..name_end];
+        let Some(&(_, key)) = XKB_KEYS.iter().find(|(n, _)| *n == key_name) else {
+            continue;
+        };
+        let Some(keysyms) = group1_keysyms(&source[block_open + 1..block_close]) else {
+            continue;
+        };
+        for (level, keysym) in keysyms.iter().take(4).enumerate() {
+            if let Some((c, dead)) = keysym_char(keysym) {
+                levels[level].push((c, key, dead));
+            }
+        }
+    }
+
+    let mut layout = Layout::new(name);
+    for (i, entries) in levels.iter().enumerate() {
+        for &(c, key, dead) in entries {
+            if dead {
+                layout.insert_dead(c, key, i + 1);
+            } else {
+                layout.insert(c, key, i + 1);
+            }
+        }
+    }
+    Ok(layout)
+}
+
+/// Returns the only character in `s`, if it has exactly one.
+fn single_char(s: &str) -> Option<char> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(c),
+        _ => None,
+    }
+}
+
+/// Deserializes the value of a TOML entry, reporting errors on its line.
+fn toml_value<T: DeserializeOwned>(
+    source: &str,
+    value: Spanned<toml::Value>,
+) -> Result<T, KeymapError> {
+    let line = line_at(source, value.span().start);
+    value
+        .into_inner()
+        .try_into()
+        .map_err(|err: toml::de::Error| syntax_error(line, err.message()))
+}
+
+/// Builds a layout from a TOML table mapping characters to chords.
+///
+/// # Errors
+///
+/// Returns `KeymapError::Syntax` for malformed TOML, unknown key names and
+/// entries that are neither a single character nor `name` or `dead`.
+pub fn from_toml(name: &str, source: &str) -> Result<Layout, KeymapError> {
+    let table: HashMap<Spanned<String>, Spanned<toml::Value>> =
+        toml::from_str(source).map_err(|err| {
+            let line = err.span().map_or(1, |span| line_at(source, span.start));
+            syntax_error(line, err.message())
+        })?;
+    // Apply entries in file order, so that earlier mappings take precedence
+    let mut entries: Vec<_> = table.into_iter().collect();
+    entries.sort_by_key(|(key, _)| key.span().start);
+
+    let mut layout = Layout::new(name);
+    let mut dead = Vec::new();
+    for (key, value) in entries {
+        let line = line_at(source, key.span().start);
+        match key.get_ref().as_str() {
+            "name" => layout.set_name(&toml_value::<String>(source, value)?),
+            "dead" => {
+                for c in toml_value::<Vec<char>>(source, value)? {
+                    dead.push((c, line));
+                }
+            }
+            key if single_char(key).is_some() => {
+                let chord = toml_value::<Vec<String>>(source, value)?
+                    .iter()
+                    .map(|name| {
+                        parse_key(name).map_err(|kind| syntax_error(line, kind.to_string()))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if chord.is_empty() {
+                    return Err(syntax_error(line, "Expected at least one key"));
+                }
+                layout.insert_chord(single_char(key).unwrap(), chord);
+            }
+            key => {
+                return Err(syntax_error(line, format!("Unknown entry: {}", key)));
+            }
+        }
+    }
+    for (c, line) in dead {
+        if !layout.mark_dead(c) {
+            return Err(syntax_error(
+                line,
+                format!("Dead key {:?} is not mapped to any keys", c),
+            ));
+        }
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const XKB: &str = r#"
+xkb_keymap {
+    xkb_keycodes "evdev" { <AD01> = 24; };
+    xkb_symbols "pc+de+inet(evdev)" {
+        name[group1]="German";
+        // Comments with { braces } are ignored
+        key <TLDE> { [ dead_circumflex, degree, U2032, U2033 ] };
+        key <AD01> { type= "FOUR_LEVEL", symbols[Group1]= [ q, Q, at, Greek_OMEGA ] };
+        key <AD07> { [ u, U, Ugrave, Udiaeresis ] };
+        key <AD11> { [ udiaeresis, Udiaeresis, dead_diaeresis ] };
+        key <AE03> { [ 3, section, threesuperior, sterling ] };
+        key <SPCE> { [ space ] };
+    };
+};
+"#;
+
+    #[test]
+    fn reads_xkb_symbols() {
+        let layout = from_xkb("de", XKB).unwrap();
+        assert_eq!(layout.chord('@'), Some(&[vec![Key::AltGr, Key::KeyQ]][..]));
+        assert_eq!(
+            layout.chord('§'),
+            Some(&[vec![Key::ShiftLeft, Key::Num3]][..])
+        );
+        assert_eq!(
+            layout.chord('′'),
+            Some(&[vec![Key::AltGr, Key::BackQuote]][..])
+        );
+        assert_eq!(layout.char_at(Key::KeyU, 3), Some('Ù'));
+        assert_eq!(layout.char_at(Key::KeyU, 4), Some('Ü'));
+        // Ü is typed with Shift on its own key rather than AltGr+Shift+U
+        assert_eq!(
+            layout.chord('Ü'),
+            Some(&[vec![Key::ShiftLeft, Key::LeftBracket]][..])
+        );
+    }
+
+    #[test]
+    fn follows_dead_keys_with_space() {
+        let layout = from_xkb("de", XKB).unwrap();
+        assert!(layout.is_dead('^'));
+        assert_eq!(
+            layout.chord('^'),
+            Some(&[vec![Key::BackQuote], vec![Key::Space]][..])
+        );
+        assert_eq!(
+            layout.chord('¨'),
+            Some(&[vec![Key::AltGr, Key::LeftBracket], vec![Key::Space]][..])
+        );
+    }
+
+    #[test]
+    fn rejects_keymaps_without_symbols() {
+        let error = from_xkb("x", "xkb_keymap {\n};").unwrap_err();
+        assert_eq!(error.to_string(), "line 1: No xkb_symbols section found");
+    }
+
+    #[test]
+    fn reads_toml_tables() {
+        let layout = from_toml(
+            "custom",
+            r#"
+name = "de-custom"
+dead = ["^"]
+
+"z" = ["KeyY"] # trailing comment
+'Z' = ["ShiftLeft", "KeyY",]
+"@" = ["AltGr", "KeyQ"]
+"^" = ["BackQuote"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(layout.name(), "de-custom");
+        assert_eq!(layout.chord('z'), Some(&[vec![Key::KeyY]][..]));
+        assert_eq!(layout.char_at(Key::KeyY, 2), Some('Z'));
+        assert_eq!(layout.char_at(Key::KeyQ, 3), Some('@'));
+        assert!(layout.is_dead('^'));
+    }
+
+    #[test]
+    fn reports_toml_errors_with_their_line() {
+        let error = |source| from_toml("x", source).unwrap_err().to_string();
+        assert_eq!(error("\n\"a\" = [\"Nope\"]"), "line 2: Unknown key: Nope");
+        assert_eq!(
+            error("\"a\" = \"KeyA\""),
+            "line 1: invalid type: string \"KeyA\", expected a sequence"
+        );
+        assert_eq!(error("\n\nfoo = [\"KeyA\"]"), "line 3: Unknown entry: foo");
+        assert_eq!(
+            error("dead = [\"q\"]"),
+            "line 1: Dead key 'q' is not mapped to any keys"
+        );
+        assert!(error("\"a\" = [").starts_with("line 1: "));
+    }
+}
@@ -0,0 +1,786 @@
+//! Keyboard layouts that translate characters into physical key chords.
+//!
+//! `rdev::Key` names physical keys by their position on a US-QWERTY keyboard,
+//! so the chord needed to type a character depends on the keymap the operating
+//! system is using. A [`Layout`] records, for each physical key, the characters
+//! it produces at each shift level:
+//!
+//! 1. No modifiers
+//! 2. Shift
+//! 3. AltGr
+//! 4. Shift+AltGr
+//!
+//! Built-in tables follow the XKB definitions of the corresponding layouts.
+//! AltGr levels holding dead keys are left out, except for the dead keys each
+//! constructor lists, and the `de` and `fr` tables only include the most
+//! common AltGr characters.
+//! Other layouts can be loaded from XKB keymaps or TOML tables with
+//! [`Layout::load`].
+//!
+//! # Examples
+//!
+//! ```
+//! use rdev::Key;
+//! use typewriter::layout::Layout;
+//!
+//! let de = Layout::by_name("de").unwrap();
+//! assert_eq!(de.chord('z'), Some(&[vec![Key::KeyY]][..]));
+//! assert_eq!(de.chord('@'), Some(&[vec![Key::AltGr, Key::KeyQ]][..]));
+//! ```
+
+use crate::keymap::{self, KeymapError};
+use rdev::Key;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// The physical keys and characters of a layout, as `(key, levels)` pairs.
+///
+/// Each character in `levels` is the output of the key at the next shift level,
+/// starting with no modifiers.
+type Table = &'static [(Key, &'static str)];
+
+const US: Table = &[
+    (Key::BackQuote, "`~"),
+    (Key::Num1, "1!"),
+    (Key::Num2, "2@"),
+    (Key::Num3, "3#"),
+    (Key::Num4, "4$"),
+    (Key::Num5, "5%"),
+    (Key::Num6, "6^"),
+    (Key::Num7, "7&"),
+    (Key::Num8, "8*"),
+    (Key::Num9, "9("),
+    (Key::Num0, "0)"),
+    (Key::Minus, "-_"),
+    (Key::Equal, "=+"),
+    (Key::KeyQ, "qQ"),
+    (Key::KeyW, "wW"),
+    (Key::KeyE, "eE"),
+    (Key::KeyR, "rR"),
+    (Key::KeyT, "tT"),
+    (Key::KeyY, "yY"),
+    (Key::KeyU, "uU"),
+    (Key::KeyI, "iI"),
+    (Key::KeyO, "oO"),
+    (Key::KeyP, "pP"),
+    (Key::LeftBracket, "[{"),
+    (Key::RightBracket, "]}"),
+    (Key::BackSlash, "\\|"),
+    (Key::KeyA, "aA"),
+    (Key::KeyS, "sS"),
+    (Key::KeyD, "dD"),
+    (Key::KeyF, "fF"),
+    (Key::KeyG, "gG"),
+    (Key::KeyH, "hH"),
+    (Key::KeyJ, "jJ"),
+    (Key::KeyK, "kK"),
+    (Key::KeyL, "lL"),
+    (Key::SemiColon, ";:"),
+    (Key::Quote, "'\""),
+    (Key::KeyZ, "zZ"),
+    (Key::KeyX, "xX"),
+    (Key::KeyC, "cC"),
+    (Key::KeyV, "vV"),
+    (Key::KeyB, "bB"),
+    (Key::KeyN, "nN"),
+    (Key::KeyM, "mM"),
+    (Key::Comma, ",<"),
+    (Key::Dot, ".>"),
+    (Key::Slash, "/?"),
+];
+
+const UK: Table = &[
+    (Key::BackQuote, "`¬||"),
+    (Key::Num1, "1!¹¡"),
+    (Key::Num2, "2\"²⅛"),
+    (Key::Num3, "3£³£"),
+    (Key::Num4, "4$€¼"),
+    (Key::Num5, "5%½⅜"),
+    (Key::Num6, "6^¾⅝"),
+    (Key::Num7, "7&{⅞"),
+    (Key::Num8, "8*[™"),
+    (Key::Num9, "9(]±"),
+    (Key::Num0, "0)}°"),
+    (Key::Minus, "-_\\¿"),
+    (Key::Equal, "=+"),
+    (Key::KeyQ, "qQ@Ω"),
+    (Key::KeyW, "wWſ§"),
+    (Key::KeyE, "eEeE"),
+    (Key::KeyR, "rR¶®"),
+    (Key::KeyT, "tTŧŦ"),
+    (Key::KeyY, "yY←¥"),
+    (Key::KeyU, "uU↓↑"),
+    (Key::KeyI, "iI→ı"),
+    (Key::KeyO, "oOøØ"),
+    (Key::KeyP, "pPþÞ"),
+    (Key::LeftBracket, "[{"),
+    (Key::RightBracket, "]}"),
+    (Key::KeyA, "aAæÆ"),
+    (Key::KeyS, "sSßẞ"),
+    (Key::KeyD, "dDðÐ"),
+    (Key::KeyF, "fFđª"),
+    (Key::KeyG, "gGŋŊ"),
+    (Key::KeyH, "hHħĦ"),
+    (Key::KeyJ, "jJ"),
+    (Key::KeyK, "kKĸ&"),
+    (Key::KeyL, "lLłŁ"),
+    (Key::SemiColon, ";:"),
+    (Key::Quote, "'@"),
+    (Key::BackSlash, "#~"),
+    (Key::IntlBackslash, "\\||¦"),
+    (Key::KeyZ, "zZ«<"),
+    (Key::KeyX, "xX»>"),
+    (Key::KeyC, "cC¢©"),
+    (Key::KeyV, "vV„‚"),
+    (Key::KeyB, "bB“‘"),
+    (Key::KeyN, "nN”’"),
+    (Key::KeyM, "mMµº"),
+    (Key::Comma, ",<•×"),
+    (Key::Dot, ".>·÷"),
+    (Key::Slash, "/?"),
+];
+
+const DE: Table = &[
+    (Key::BackQuote, "^°"),
+    (Key::Num1, "1!"),
+    (Key::Num2, "2\"²"),
+    (Key::Num3, "3§³"),
+    (Key::Num4, "4$"),
+    (Key::Num5, "5%"),
+    (Key::Num6, "6&"),
+    (Key::Num7, "7/{"),
+    (Key::Num8, "8(["),
+    (Key::Num9, "9)]"),
+    (Key::Num0, "0=}"),
+    (Key::Minus, "ß?\\"),
+    (Key::Equal, "´`"),
+    (Key::KeyQ, "qQ@"),
+    (Key::KeyW, "wW"),
+    (Key::KeyE, "eE€"),
+    (Key::KeyR, "rR"),
+    (Key::KeyT, "tT"),
+    (Key::KeyY, "zZ"),
+    (Key::KeyU, "uU"),
+    (Key::KeyI, "iI"),
+    (Key::KeyO, "oO"),
+    (Key::KeyP, "pP"),
+    (Key::LeftBracket, "üÜ"),
+    (Key::RightBracket, "+*~"),
+    (Key::KeyA, "aA"),
+    (Key::KeyS, "sS"),
+    (Key::KeyD, "dD"),
+    (Key::KeyF, "fF"),
+    (Key::KeyG, "gG"),
+    (Key::KeyH, "hH"),
+    (Key::KeyJ, "jJ"),
+    (Key::KeyK, "kK"),
+    (Key::KeyL, "lL"),
+    (Key::SemiColon, "öÖ"),
+    (Key::Quote, "äÄ"),
+    (Key::BackSlash, "#'"),
+    (Key::IntlBackslash, "<>|"),
+    (Key::KeyZ, "yY"),
+    (Key::KeyX, "xX"),
+    (Key::KeyC, "cC"),
+    (Key::KeyV, "vV"),
+    (Key::KeyB, "bB"),
+    (Key::KeyN, "nN"),
+    (Key::KeyM, "mMµ"),
+    (Key::Comma, ",;"),
+    (Key::Dot, ".:"),
+    (Key::Slash, "-_"),
+];
+
+const FR: Table = &[
+    (Key::BackQuote, "²"),
+    (Key::Num1, "&1"),
+    (Key::Num2, "é2~"),
+    (Key::Num3, "\"3#"),
+    (Key::Num4, "'4{"),
+    (Key::Num5, "(5["),
+    (Key::Num6, "-6|"),
+    (Key::Num7, "è7`"),
+    (Key::Num8, "_8\\"),
+    (Key::Num9, "ç9^"),
+    (Key::Num0, "à0@"),
+    (Key::Minus, ")°]"),
+    (Key::Equal, "=+}"),
+    (Key::KeyQ, "aA"),
+    (Key::KeyW, "zZ"),
+    (Key::KeyE, "eE€"),
+    (Key::KeyR, "rR"),
+    (Key::KeyT, "tT"),
+    (Key::KeyY, "yY"),
+    (Key::KeyU, "uU"),
+    (Key::KeyI, "iI"),
+    (Key::KeyO, "oO"),
+    (Key::KeyP, "pP"),
+    (Key::LeftBracket, "^¨"),
+    (Key::RightBracket, "$£¤"),
+    (Key::KeyA, "qQ"),
+    (Key::KeyS, "sS"),
+    (Key::KeyD, "dD"),
+    (Key::KeyF, "fF"),
+    (Key::KeyG, "gG"),
+    (Key::KeyH, "hH"),
+    (Key::KeyJ, "jJ"),
+    (Key::KeyK, "kK"),
+    (Key::KeyL, "lL"),
+    (Key::SemiColon, "mM"),
+    (Key::Quote, "ù%"),
+    (Key::BackSlash, "*µ"),
+    (Key::IntlBackslash, "<>"),
+    (Key::KeyZ, "wW"),
+    (Key::KeyX, "xX"),
+    (Key::KeyC, "cC"),
+    (Key::KeyV, "vV"),
+    (Key::KeyB, "bB"),
+    (Key::KeyN, "nN"),
+    (Key::KeyM, ",?"),
+    (Key::Comma, ";."),
+    (Key::Dot, ":/"),
+    (Key::Slash, "!§"),
+];
+
+const DVORAK: Table = &[
+    (Key::BackQuote, "`~"),
+    (Key::Num1, "1!"),
+    (Key::Num2, "2@"),
+    (Key::Num3, "3#"),
+    (Key::Num4, "4$"),
+    (Key::Num5, "5%"),
+    (Key::Num6, "6^"),
+    (Key::Num7, "7&"),
+    (Key::Num8, "8*"),
+    (Key::Num9, "9("),
+    (Key::Num0, "0)"),
+    (Key::Minus, "[{"),
+    (Key::Equal, "]}"),
+    (Key::KeyQ, "'\""),
+    (Key::KeyW, ",<"),
+    (Key::KeyE, ".>"),
+    (Key::KeyR, "pP"),
+    (Key::KeyT, "yY"),
+    (Key::KeyY, "fF"),
+    (Key::KeyU, "gG"),
+    (Key::KeyI, "cC"),
+    (Key::KeyO, "rR"),
+    (Key::KeyP, "lL"),
+    (Key::LeftBracket, "/?"),
+    (Key::RightBracket, "=+"),
+    (Key::BackSlash, "\\|"),
+    (Key::KeyA, "aA"),
+    (Key::KeyS, "oO"),
+    (Key::KeyD, "eE"),
+    (Key::KeyF, "uU"),
+    (Key::KeyG, "iI"),
+    (Key::KeyH, "dD"),
+    (Key::KeyJ, "hH"),
+    (Key::KeyK, "tT"),
+    (Key::KeyL, "nN"),
+    (Key::SemiColon, "sS"),
+    (Key::Quote, "-_"),
+    (Key::KeyZ, ";:"),
+    (Key::KeyX, "qQ"),
+    (Key::KeyC, "jJ"),
+    (Key::KeyV, "kK"),
+    (Key::KeyB, "xX"),
+    (Key::KeyN, "bB"),
+    (Key::KeyM, "mM"),
+    (Key::Comma, "wW"),
+    (Key::Dot, "vV"),
+    (Key::Slash, "zZ"),
+];
+
+/// Names of the built-in layouts, as accepted by [`Layout::by_name`].
+pub const LAYOUT_NAMES: &[&str] = &["us", "uk", "de", "fr", "dvorak"];
+
+/// The rows of the main block of keys, from the top, as the horizontal position
+/// of the first key (in quarter key widths) and the keys from left to right.
+const ROWS: &[(i32, &[Key])] = &[
+    (
+        0,
+        &[
+            Key::BackQuote,
+            Key::Num1,
+            Key::Num2,
+            Key::Num3,
+            Key::Num4,
+            Key::Num5,
+            Key::Num6,
+            Key::Num7,
+            Key::Num8,
+            Key::Num9,
+            Key::Num0,
+            Key::Minus,
+            Key::Equal,
+        ],
+    ),
+    (
+        6,
+        &[
+            Key::KeyQ,
+            Key::KeyW,
+            Key::KeyE,
+            Key::KeyR,
+            Key::KeyT,
+            Key::KeyY,
+            Key::KeyU,
+            Key::KeyI,
+            Key::KeyO,
+            Key::KeyP,
+            Key::LeftBracket,
+            Key::RightBracket,
+            Key::BackSlash,
+        ],
+    ),
+    (
+        7,
+        &[
+            Key::KeyA,
+            Key::KeyS,
+            Key::KeyD,
+            Key::KeyF,
+            Key::KeyG,
+            Key::KeyH,
+            Key::KeyJ,
+            Key::KeyK,
+            Key::KeyL,
+            Key::SemiColon,
+            Key::Quote,
+        ],
+    ),
+    (
+        5,
+        &[
+            Key::IntlBackslash,
+            Key::KeyZ,
+            Key::KeyX,
+            Key::KeyC,
+            Key::KeyV,
+            Key::KeyB,
+            Key::KeyN,
+            Key::KeyM,
+            Key::Comma,
+            Key::Dot,
+            Key::Slash,
+        ],
+    ),
+];
+
+/// Returns the row and horizontal position (in quarter key widths) of `key`.
+fn key_position(key: Key) -> Option<(i32, i32)> {
+    ROWS.iter().zip(0..).find_map(|((offset, keys), row)| {
+        let column = keys.iter().position(|k| *k == key)?;
+        Some((row, offset + 4 * column as i32))
+    })
+}
+
+/// Plain substitutes for characters that are often missing from a layout.
+const FALLBACKS: &[(&str, &str)] = &[
+    ("ÀÁÂÃÄÅĀĂĄ", "A"),
+    ("àáâãäåāăą", "a"),
+    ("ÇĆĈĊČ", "C"),
+    ("çćĉċč", "c"),
+    ("ÐĎĐ", "D"),
+    ("ðďđ", "d"),
+    ("ÈÉÊËĒĔĖĘĚ", "E"),
+    ("èéêëēĕėęě", "e"),
+    ("ĜĞĠĢ", "G"),
+    ("ĝğġģ", "g"),
+    ("ÌÍÎÏĨĪĬĮİ", "I"),
+    ("ìíîïĩīĭįı", "i"),
+    ("ŁĹĻĽ", "L"),
+    ("łĺļľ", "l"),
+    ("ÑŃŅŇ", "N"),
+    ("ñńņň", "n"),
+    ("ÒÓÔÕÖØŌŎŐ", "O"),
+    ("òóôõöøōŏő", "o"),
+    ("ŔŖŘ", "R"),
+    ("ŕŗř", "r"),
+    ("ŚŜŞŠ", "S"),
+    ("śŝşš", "s"),
+    ("ŢŤ", "T"),
+    ("ţť", "t"),
+    ("ÙÚÛÜŨŪŬŮŰŲ", "U"),
+    ("ùúûüũūŭůűų", "u"),
+    ("ÝŸ", "Y"),
+    ("ýÿ", "y"),
+    ("ŹŻŽ", "Z"),
+    ("źżž", "z"),
+    ("Æ", "AE"),
+    ("æ", "ae"),
+    ("Œ", "OE"),
+    ("œ", "oe"),
+    ("ß", "ss"),
+    ("‘’‚′´`", "'"),
+    ("“”„″«»", "\""),
+    ("‐‑‒–—−", "-"),
+    ("…", "..."),
+    ("\u{a0}\u{2002}\u{2003}\u{2009}", " "),
+    ("×", "x"),
+    ("•·", "*"),
+    ("€", "EUR"),
+    ("£", "GBP"),
+    ("©", "(c)"),
+    ("®", "(R)"),
+    ("™", "TM"),
+];
+
+/// The keypad keys for the digits 0 to 9.
+pub(crate) const KEYPAD_DIGITS: [Key; 10] = [
+    Key::Kp0,
+    Key::Kp1,
+    Key::Kp2,
+    Key::Kp3,
+    Key::Kp4,
+    Key::Kp5,
+    Key::Kp6,
+    Key::Kp7,
+    Key::Kp8,
+    Key::Kp9,
+];
+
+/// Returns the shift level (1 to 4) selected by holding Shift and AltGr.
+pub(crate) fn level(shift: bool, alt_gr: bool) -> usize {
+    match (shift, alt_gr) {
+        (false, false) => 1,
+        (true, false) => 2,
+        (false, true) => 3,
+        (true, true) => 4,
+    }
+}
+
+/// Returns the modifiers that select a shift level (1-based), the inverse
+/// of [`level`].
+fn level_modifiers(level: usize) -> &'static [Key] {
+    match level {
+        1 => &[],
+        2 => &[Key::ShiftLeft],
+        3 => &[Key::AltGr],
+        _ => &[Key::ShiftLeft, Key::AltGr],
+    }
+}
+
+/// A keyboard layout mapping characters to the keys that produce them.
+#[derive(Debug, Clone)]
+pub struct Layout {
+    name: String,
+    /// The chords to type for each character, in order.
+    chords: HashMap<char, Vec<Vec<Key>>>,
+    /// The character produced by each key at each shift level.
+    chars: HashMap<(Key, usize), char>,
+    /// Characters produced by dead keys, which must be followed by Space.
+    dead: Vec<char>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self::us()
+    }
+}
+
+impl Layout {
+    /// Creates an empty layout in which only Space, Return (`\n`) and Tab
+    /// (`\t`) produce characters.
+    pub fn new(name: &str) -> Self {
+        let mut layout = Self {
+            name: name.to_string(),
+            chords: HashMap::new(),
+            chars: HashMap::new(),
+            dead: Vec::new(),
+        };
+        layout.insert(' ', Key::Space, 1);
+        layout.insert('\n', Key::Return, 1);
+        layout.insert('\t', Key::Tab, 1);
+        layout
+    }
+
+    fn from_table(name: &str, table: Table, dead: &[char]) -> Self {
+        let mut layout = Self::new(name);
+        // Insert level by level, so a character on several keys is typed
+        // with the fewest modifiers
+        for level in 1..=4 {
+            for (key, levels) in table {
+                if let Some(c) = levels.chars().nth(level - 1) {
+                    layout.insert(c, *key, level);
+                }
+            }
+        }
+        for c in dead {
+            layout.mark_dead(*c);
+        }
+        layout
+    }
+
+    /// Loads a layout from an XKB keymap or TOML file; see [`crate::keymap`].
+    ///
+    /// # Errors
+    ///
+    /// Returns `KeymapError` if the file cannot be read or parsed.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, KeymapError> {
+        keymap::load(path.as_ref())
+    }
+
+    /// Looks up a built-in layout by name or, failing that, loads the keymap
+    /// file at that path.
+    ///
+    /// # Errors
+    ///
+    /// Returns a message naming the built-in layouts if `spec` is neither a
+    /// layout name nor an existing file, or describing why the file could
+    /// not be loaded.
+    pub fn resolve(spec: &str) -> Result<Self, String> {
+        if let Some(layout) = Self::by_name(spec) {
+            return Ok(layout);
+        }
+        if !Path::new(spec).is_file() {
+            return Err(format!(
+                "Unknown layout {:?} (expected one of: {}, or a keymap file)",
+                spec,
+                LAYOUT_NAMES.join(", ")
+            ));
+        }
+        Self::load(spec).map_err(|err| format!("Could not load layout {}: {}", spec, err))
+    }
+
+    /// Maps `c` to `key` pressed at the given shift level (1 to 4).
+    ///
+    /// If the character can already be typed, the existing mapping is kept,
+    /// so lower levels and earlier keys take precedence.
+    pub fn insert(&mut self, c: char, key: Key, level: usize) {
+        self.chars.entry((key, level)).or_insert(c);
+        self.chords.entry(c).or_insert_with(|| {
+            let mut chord = level_modifiers(level).to_vec();
+            chord.push(key);
+            vec![chord]
+        });
+    }
+
+    /// Maps `c` to a dead key pressed at the given shift level (1 to 4).
+    ///
+    /// The character is typed by following the dead key with Space.
+    pub fn insert_dead(&mut self, c: char, key: Key, level: usize) {
+        if self.chords.contains_key(&c) {
+            self.chars.entry((key, level)).or_insert(c);
+        } else {
+            self.insert(c, key, level);
+            self.mark_dead(c);
+        }
+    }
+
+    /// Maps `c` to an arbitrary chord, whose last key is the one producing it.
+    ///
+    /// If the other keys only select a shift level (Shift and AltGr), the key
+    /// is also recorded as producing `c` at that level. As with
+    /// [`Layout::insert`], an existing mapping for `c` is kept.
+    pub fn insert_chord(&mut self, c: char, chord: Vec<Key>) {
+        let Some((&key, modifiers)) = chord.split_last() else {
+            return;
+        };
+        let shift = modifiers
+            .iter()
+            .any(|k| matches!(k, Key::ShiftLeft | Key::ShiftRight));
+        let alt_gr = modifiers.contains(&Key::AltGr);
+        let level_only = modifiers
+            .iter()
+            .all(|k| matches!(k, Key::ShiftLeft | Key::ShiftRight | Key::AltGr));
+        if level_only {
+            self.chars.entry((key, level(shift, alt_gr))).or_insert(c);
+        }
+        self.chords.entry(c).or_insert_with(|| vec![chord]);
+    }
+
+    /// Marks `c` as produced by a dead key, so that it is followed by Space.
+    ///
+    /// Returns false if `c` cannot be typed with this layout.
+    pub(crate) fn mark_dead(&mut self, c: char) -> bool {
+        let Some(chords) = self.chords.get_mut(&c) else {
+            return false;
+        };
+        if !self.dead.contains(&c) {
+            chords.push(vec![Key::Space]);
+            self.dead.push(c);
+        }
+        true
+    }
+
+    pub(crate) fn set_name(&mut self, name: &str) {
+        self.name = name.to_string();
+    }
+
+    /// US-QWERTY.
+    pub fn us() -> Self {
+        Self::from_table("us", US, &[])
+    }
+
+    /// UK-QWERTY, with the AltGr levels of XKB's `gb` layout.
+    pub fn uk() -> Self {
+        Self::from_table("uk", UK, &[])
+    }
+
+    /// German QWERTZ, with dead `^`, `´` and `` ` ``.
+    pub fn de() -> Self {
+        Self::from_table("de", DE, &['^', '´', '`'])
+    }
+
+    /// French AZERTY, with dead `^` and `¨`.
+    pub fn fr() -> Self {
+        Self::from_table("fr", FR, &['^', '¨'])
+    }
+
+    /// US Dvorak.
+    pub fn dvorak() -> Self {
+        Self::from_table("dvorak", DVORAK, &[])
+    }
+
+    /// Looks up a built-in layout by name, ignoring case.
+    ///
+    /// `gb` is accepted as an alias for `uk`.
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "us" => Some(Self::us()),
+            "uk" | "gb" => Some(Self::uk()),
+            "de" => Some(Self::de()),
+            "fr" => Some(Self::fr()),
+            "dvorak" => Some(Self::dvorak()),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of this layout.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the chords to type, in order, to produce `c`.
+    ///
+    /// Most characters need a single chord; characters on dead keys are
+    /// followed by Space. Returns `None` if the layout cannot produce `c`.
+    pub fn chord(&self, c: char) -> Option<&[Vec<Key>]> {
+        self.chords.get(&c).map(Vec::as_slice)
+    }
+
+    /// Returns the character produced by `key` at the given shift level.
+    pub fn char_at(&self, key: Key, level: usize) -> Option<char> {
+        self.chars.get(&(key, level)).copied()
+    }
+
+    /// Returns the characters typed by the keys physically next to the one
+    /// that types `c`, at the same shift level.
+    ///
+    /// Characters on dead keys are left out. Returns an empty list if `c` is
+    /// not typed by a key in the main block.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use typewriter::layout::Layout;
+    ///
+    /// assert_eq!(Layout::us().neighbors('G'), vec!['T', 'Y', 'F', 'H', 'V', 'B']);
+    /// ```
+    pub fn neighbors(&self, c: char) -> Vec<char> {
+        let Some(&key) = self.chord(c).and_then(|chords| chords[0].last()) else {
+            return Vec::new();
+        };
+        let Some(level) = (1..=4).find(|level| self.char_at(key, *level) == Some(c)) else {
+            return Vec::new();
+        };
+        let Some((row, x)) = key_position(key) else {
+            return Vec::new();
+        };
+        ROWS.iter()
+            .flat_map(|(_, keys)| keys.iter())
+            .filter(|other| match key_position(**other) {
+                Some((r, ox)) if r == row => (ox - x).abs() == 4,
+                Some((r, ox)) => (r - row).abs() == 1 && (ox - x).abs() < 4,
+                None => false,
+            })
+            .filter_map(|other| self.char_at(*other, level))
+            .filter(|neighbor| !self.is_dead(*neighbor))
+            .collect()
+    }
+
+    /// Returns a substitute for `c` that this layout can type, such as `e` for
+    /// `é` or `...` for `…`, falling back to `?`.
+    ///
+    /// Returns `None` if neither a substitute nor `?` can be typed.
+    pub fn fallback(&self, c: char) -> Option<&'static str> {
+        FALLBACKS
+            .iter()
+            .find(|(chars, _)| chars.contains(c))
+            .map(|(_, substitute)| *substitute)
+            .into_iter()
+            .chain(["?"])
+            .find(|substitute| substitute.chars().all(|c| self.chords.contains_key(&c)))
+    }
+
+    /// Returns true if `c` is produced by a dead key in this layout.
+    pub fn is_dead(&self, c: char) -> bool {
+        self.dead.contains(&c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uk_alt_gr_levels_match_xkb() {
+        let uk = Layout::uk();
+        assert_eq!(uk.char_at(Key::KeyE, 3), Some('e'));
+        assert_eq!(uk.char_at(Key::KeyU, 3), Some('↓'));
+        assert_eq!(uk.char_at(Key::KeyI, 3), Some('→'));
+        assert_eq!(uk.char_at(Key::KeyO, 3), Some('ø'));
+        assert_eq!(uk.char_at(Key::KeyA, 3), Some('æ'));
+        assert_eq!(uk.char_at(Key::BackQuote, 3), Some('|'));
+        assert_eq!(uk.chord('é'), None);
+    }
+
+    #[test]
+    fn prefers_the_fewest_modifiers() {
+        let uk = Layout::uk();
+        assert_eq!(
+            uk.chord('|'),
+            Some(&[vec![Key::ShiftLeft, Key::IntlBackslash]][..])
+        );
+        assert_eq!(uk.chord('<'), Some(&[vec![Key::ShiftLeft, Key::Comma]][..]));
+        assert_eq!(uk.chord('£'), Some(&[vec![Key::ShiftLeft, Key::Num3]][..]));
+    }
+
+    #[test]
+    fn resolves_names_and_reports_unknown_ones() {
+        assert_eq!(Layout::resolve("GB").unwrap().name(), "uk");
+        assert_eq!(
+            Layout::resolve("xx").unwrap_err(),
+            "Unknown layout \"xx\" (expected one of: us, uk, de, fr, dvorak, or a keymap file)"
+        );
+    }
+
+    #[test]
+    fn falls_back_to_typeable_substitutes() {
+        let us = Layout::us();
+        assert_eq!(us.fallback('é'), Some("e"));
+        assert_eq!(us.fallback('…'), Some("..."));
+        assert_eq!(us.fallback('→'), Some("?"));
+        assert_eq!(Layout::new("empty").fallback('é'), None);
+    }
+
+    #[test]
+    fn maps_shift_levels_to_modifiers_and_back() {
+        for expected in 1..=4 {
+            let modifiers = level_modifiers(expected);
+            let shift = modifiers.contains(&Key::ShiftLeft);
+            let alt_gr = modifiers.contains(&Key::AltGr);
+            assert_eq!(level(shift, alt_gr), expected);
+        }
+    }
+
+    #[test]
+    fn neighbors_skip_dead_keys() {
+        let de = Layout::de();
+        assert!(de.neighbors('1').contains(&'2'));
+        assert!(!de.neighbors('1').contains(&'^'));
+        assert_eq!(Layout::us().neighbors('\n'), Vec::<char>::new());
+    }
+}
@@ -0,0 +1,15 @@
+//! Have a computer type for you.
+//!
+//! Typewriter is a keyboard automation tool with a simple DSL. Scripts can be
+//! interpreted at runtime with [`parser::run_script`] or embedded in Rust code
+//! with the [`script!`] macro.
+
+pub mod backend;
+pub mod config;
+pub mod editor;
+pub mod humanize;
+pub mod keymap;
+pub mod layout;
+pub mod macros;
+pub mod parser;
+pub mod typewriter;
@@ -3,15 +3,20 @@
 //! This module provides the `script!` macro and supporting traits that allow
 //! you to write keyboard automation scripts using a clean, concise syntax:
 //!
-//! ```rust
+//! ```no_run
+//! # use rdev::Key;
+//! # use typewriter::config::Speed;
+//! # use typewriter::script;
 //! script! {
 //!     "Hello, World!";          // Types the string with default 50ms delay
 //!     100;                       // Sleeps for 100ms
 //!     [Key::ControlLeft, Key::KeyA];  // Types a chord (Ctrl+A)
 //!     ("Custom delay", 100);    // Types with custom 100ms delay per character
+//!     ("Steady", Speed::Wpm(90));  // Types at 90 words per minute
 //! }
 //! ```
 
+use crate::config::{Config, Speed};
 use crate::typewriter::{sleep, type_string};
 
 /// Trait for dispatching script actions based on expression type.
@@ -22,30 +27,55 @@ pub trait ScriptAction {
     fn run(self);
 }
 
-// String slice -> type_string with default 50ms delay
+/// Types `s` with the default configuration, at `speed` or the default speed.
+///
+/// A string containing characters the layout cannot type is skipped with an
+/// error message, since a script has no way to handle the error.
+fn type_text(s: String, speed: Option<Speed>) {
+    let config = Config::default();
+    let delay = speed.unwrap_or(config.speed).char_delay(config.key_delay);
+    if let Err(err) = type_string(s, delay, &config) {
+        eprintln!("Skipping text: {}", err);
+    }
+}
+
+// String slice -> type_string at the default speed
 impl ScriptAction for &str {
     fn run(self) {
-        type_string(self.to_string(), 50);
+        type_text(self.to_string(), None);
     }
 }
 
-// Owned String -> type_string with default 50ms delay
+// Owned String -> type_string at the default speed
 impl ScriptAction for String {
     fn run(self) {
-        type_string(self, 50);
+        type_text(self, None);
     }
 }
 
 // Tuple of (string, delay) -> type_string with custom delay
 impl ScriptAction for (&str, u64) {
     fn run(self) {
-        type_string(self.0.to_string(), self.1);
+        type_text(self.0.to_string(), Some(Speed::Millis(self.1)));
     }
 }
 
 impl ScriptAction for (String, u64) {
     fn run(self) {
-        type_string(self.0, self.1);
+        type_text(self.0, Some(Speed::Millis(self.1)));
+    }
+}
+
+// Tuple of (string, speed) -> type_string at a custom speed, such as words per minute
+impl ScriptAction for (&str, Speed) {
+    fn run(self) {
+        type_text(self.0.to_string(), Some(self.1));
+    }
+}
+
+impl ScriptAction for (String, Speed) {
+    fn run(self) {
+        type_text(self.0, Some(self.1));
     }
 }
 
@@ -86,13 +116,14 @@ impl ScriptAction for i64 {
 ///
 /// The macro accepts a sequence of statements separated by semicolons:
 /// - **String literals** (`"text";`) - Types the text with 50ms delay per character
-/// - **Tuples** (`("text", delay);`) - Types the text with custom delay per character
+/// - **Tuples** (`("text", delay);`) - Types the text with custom delay per character,
+///   or at a [`Speed`](crate::config::Speed) such as `Speed::Wpm(90)`
 /// - **Integers** (`100;`) - Sleeps for that many milliseconds
 /// - **Arrays of Keys** (`[Key::A, Key::B];`) - Types a key chord
 ///
 /// # Examples
 ///
-/// ```rust
+/// ```no_run
 /// use typewriter::script;
 /// use rdev::Key;
 ///
@@ -125,6 +156,10 @@ impl ScriptAction for i64 {
 ///    - Strings become `type_string` calls
 ///    - Integers become `sleep` calls
 ///    - Tuples become `type_string` calls with custom delays
+///
+/// Text is typed using the default [`Config`](crate::config::Config), which
+/// uses the US-QWERTY layout. Strings containing characters it cannot type
+/// are skipped with an error message.
 #[macro_export]
 macro_rules! script {
     // Base case - no more tokens
@@ -133,7 +168,7 @@ macro_rules! script {
     // Match array of keys -> type_chord
     // This must come before the general expression case
     ([$($key:expr),* $(,)?]; $($rest:tt)*) => {
-        $crate::typewriter::type_chord(vec![$($key),*]);
+        $crate::typewriter::type_chord(vec![$($key),*], &$crate::config::Config::default());
         $crate::script!($($rest)*);
     };
 
@@ -143,3 +178,38 @@ macro_rules! script {
         $crate::script!($($rest)*);
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::Recorder;
+    use crate::editor::Editor;
+    use rdev::Key;
+
+    #[test]
+    fn runs_each_statement() {
+        let editor = Editor::new();
+        editor.record(|| {
+            script! {
+                "ab";
+                ("cd", 10);
+                ("ef", crate::config::Speed::Wpm(600));
+                [Key::Backspace];
+                "café";
+            }
+        });
+        assert_eq!(editor.text(), "abcde");
+    }
+
+    #[test]
+    fn sleeps_for_integers() {
+        let recorder = Recorder::new();
+        recorder.record(|| {
+            script! {
+                100;
+                250u64;
+            }
+        });
+        assert_eq!(recorder.elapsed(), 350);
+        assert!(recorder.events().is_empty());
+    }
+}
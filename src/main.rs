@@ -1,13 +1,17 @@
-mod macros;
-mod parser;
-mod typewriter;
-
 use clap::Parser as ClapParser;
 use rdev::{Button, Event, EventType, listen};
 use std::fs;
+use std::panic;
 use std::path::PathBuf;
 use std::process;
 use std::thread;
+use typewriter::backend::{RdevBackend, Recorder};
+use typewriter::config::{
+    CarriageReturn, Config, Indent, Newline, Speed, UnicodeInput, Unsupported, Variables,
+};
+use typewriter::humanize::{Distribution, Humanize, Rng, Typos};
+use typewriter::layout::Layout;
+use typewriter::parser::{self, Action};
 
 #[derive(ClapParser, Debug)]
 #[command(name = "typewriter")]
@@ -24,37 +28,325 @@ struct Args {
     /// Disable mouse click interrupt
     #[arg(long)]
     no_interrupt: bool,
+
+    /// Print the parsed actions and their timing without sending any keys
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Check the script for errors without running it
+    #[arg(long)]
+    check: bool,
+
+    /// Keyboard layout used by the operating system (us, uk, de, fr, dvorak),
+    /// or the path to an XKB keymap or TOML keymap file
+    #[arg(long, value_name = "LAYOUT", default_value = "us", value_parser = Layout::resolve)]
+    layout: Layout,
+
+    /// Input method for characters not on the layout (none, ibus, macos, windows)
+    #[arg(long, value_name = "METHOD", default_value = "none", value_parser = UnicodeInput::by_name)]
+    unicode: UnicodeInput,
+
+    /// Chord typed for newlines in strings (return, shift+return)
+    #[arg(long, value_name = "CHORD", default_value = "return", value_parser = Newline::by_name)]
+    newline: Newline,
+
+    /// What to type for carriage returns in strings (ignore, newline)
+    #[arg(long, value_name = "ACTION", default_value = "ignore", value_parser = CarriageReturn::by_name)]
+    carriage_return: CarriageReturn,
+
+    /// How indentation after newlines is typed (keep, strip, relative)
+    #[arg(long, value_name = "MODE", default_value = "keep", value_parser = Indent::by_name)]
+    indent: Indent,
+
+    /// Type strings without a delay of their own at this many words per minute
+    #[arg(long, value_name = "WPM", value_parser = clap::value_parser!(u64).range(1..))]
+    wpm: Option<u64>,
+
+    /// Vary the delay between characters like a person typing (off, uniform, normal)
+    #[arg(long, value_name = "DISTRIBUTION", default_value = "off", value_parser = Distribution::by_name)]
+    humanize: Distribution,
+
+    /// Percentage of characters to mistype and correct straight away
+    #[arg(long, value_name = "PERCENT", default_value_t = 0, value_parser = clap::value_parser!(u64).range(0..=100))]
+    typo_rate: u64,
+
+    /// Seed for humanized typing and typos, to make runs reproducible
+    #[arg(long, value_name = "SEED")]
+    seed: Option<u64>,
+
+    /// What to do with characters that cannot be typed (error, warn, skip, fallback)
+    #[arg(long, value_name = "POLICY", default_value = "error", value_parser = Unsupported::by_name)]
+    unsupported: Unsupported,
+
+    /// Set a variable used as ${NAME} in strings, overriding `let` in the script
+    #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_variable)]
+    variables: Vec<(String, String)>,
+}
+
+/// Parses a `NAME=VALUE` pair given with `--var`.
+fn parse_variable(arg: &str) -> Result<(String, String), String> {
+    let Some((name, value)) = arg.split_once('=') else {
+        return Err(format!("Expected NAME=VALUE, got {:?}", arg));
+    };
+    if !Variables::is_valid_name(name) {
+        return Err(format!("Invalid variable name: {:?}", name));
+    }
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Releases any keys still held on the operating system, then exits.
+fn exit(code: i32) -> ! {
+    RdevBackend::shutdown();
+    process::exit(code);
+}
+
+/// Makes sure held keys are released if the script panics or the process
+/// receives Ctrl-C (SIGINT) or SIGTERM. On Windows, Ctrl-C, Ctrl-Break and
+/// closing the console window release them too.
+///
+/// Must be called before any other threads are spawned, so that they inherit
+/// the blocked signal mask and signals are delivered to the waiting thread.
+fn install_release_guards() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        RdevBackend::shutdown();
+        default_hook(info);
+    }));
+
+    #[cfg(unix)]
+    {
+        // SAFETY: the signal set is initialised by sigemptyset before use, and
+        // blocking signals here only changes how they are delivered.
+        let signals = unsafe {
+            let mut signals: libc::sigset_t = std::mem::zeroed();
+            libc::sigemptyset(&mut signals);
+            libc::sigaddset(&mut signals, libc::SIGINT);
+            libc::sigaddset(&mut signals, libc::SIGTERM);
+            libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+            signals
+        };
+        thread::spawn(move || {
+            let mut signal = 0;
+            // SAFETY: `signals` is a valid, initialised signal set.
+            if unsafe { libc::sigwait(&signals, &mut signal) } == 0 {
+                eprintln!("\nReceived signal {}, releasing held keys...", signal);
+                exit(128 + signal);
+            }
+        });
+    }
+
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::Foundation::BOOL;
+        use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+
+        // Windows runs console control handlers on a thread of their own
+        unsafe extern "system" fn handler(event: u32) -> BOOL {
+            eprintln!("\nReceived console event {}, releasing held keys...", event);
+            exit(130);
+        }
+
+        // SAFETY: `handler` is a valid handler routine for the whole process lifetime.
+        if unsafe { SetConsoleCtrlHandler(Some(handler), 1) } == 0 {
+            eprintln!("We could not install a console control handler");
+        }
+    }
+}
+
+/// Formats a count with the singular or plural form of a noun, e.g. `2 errors`.
+fn count(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("1 {}", noun),
+        n => format!("{} {}s", n, noun),
+    }
+}
+
+/// Prints every problem as a diagnostic, then exits with a failure status if
+/// any of them is an error.
+fn report_problems(problems: &[parser::ParseError], source: &str, name: &str) {
+    if problems.is_empty() {
+        return;
+    }
+    for problem in problems {
+        eprintln!("{}\n", problem.render(source, name));
+    }
+    let errors = problems.iter().filter(|p| p.is_error()).count();
+    let warnings = problems.len() - errors;
+    match (errors, warnings) {
+        (0, w) => eprintln!("Found {} in {}", count(w, "warning"), name),
+        (e, 0) => eprintln!("Found {} in {}", count(e, "error"), name),
+        (e, w) => eprintln!(
+            "Found {} and {} in {}",
+            count(e, "error"),
+            count(w, "warning"),
+            name
+        ),
+    }
+    if errors > 0 {
+        exit(1);
+    }
+}
+
+/// Formats a millisecond count as seconds, e.g. `1.250s`.
+fn format_millis(millis: u64) -> String {
+    format!("{}.{:03}s", millis / 1000, millis % 1000)
+}
+
+/// Prints each action in the script with its line number and start time.
+///
+/// Timing is measured by running the actions against a `Recorder`, so no
+/// keyboard events reach the operating system.
+fn print_plan(script: &str, mut config: Config) -> Result<(), Vec<parser::ParseError>> {
+    let actions = parser::parse_script(script)?;
+    let recorder = Recorder::new();
+
+    println!("{:>5}  {:>9}  Action", "Line", "Start");
+    if print_actions(&actions, script, &recorder, &mut config, 0).map_err(|err| vec![err])? {
+        println!("Total duration: {}", format_millis(recorder.elapsed()));
+    } else {
+        println!("Total duration: unlimited (the loop runs until interrupted)");
+    }
+    Ok(())
+}
+
+/// Prints and runs `actions`, indenting them by `depth` levels.
+///
+/// The body of a block is printed for its first iteration only, and the
+/// remaining iterations run silently. Returns false if the actions never
+/// finish because of an endless loop, in which case nothing after it is
+/// printed, or the error of the first action that fails.
+fn print_actions(
+    actions: &[parser::Spanned<Action>],
+    script: &str,
+    recorder: &Recorder,
+    config: &mut Config,
+    depth: usize,
+) -> Result<bool, parser::ParseError> {
+    for action in actions {
+        let start = recorder.elapsed();
+        // Strings without a speed of their own are typed at the current one
+        let description = match &action.node {
+            Action::TypeString(_, None) => format!("{} ({})", action.node, config.speed),
+            node => node.to_string(),
+        };
+        println!(
+            "{:>5}  {:>9}  {}{}",
+            action.span.line(script),
+            format_millis(start),
+            "  ".repeat(depth),
+            description
+        );
+        match &action.node {
+            Action::Repeat(count, body) => {
+                if *count > 0 && !print_actions(body, script, recorder, config, depth + 1)? {
+                    return Ok(false);
+                }
+                // The plan never looks at the events, so they are discarded
+                // to keep long blocks from filling up memory
+                recorder.record(|| {
+                    for _ in 1..*count {
+                        parser::execute_actions(body, config)?;
+                        recorder.discard_events();
+                    }
+                    Ok(())
+                })?;
+            }
+            Action::Loop(Some(ms), body) => {
+                if *ms > 0 && !print_actions(body, script, recorder, config, depth + 1)? {
+                    return Ok(false);
+                }
+                // Like the script itself, stop once an iteration takes no time
+                recorder.record(|| {
+                    let mut before = start;
+                    while recorder.elapsed() > before && recorder.elapsed() - start < *ms {
+                        before = recorder.elapsed();
+                        parser::execute_actions(body, config)?;
+                        recorder.discard_events();
+                    }
+                    Ok(())
+                })?;
+            }
+            Action::Loop(None, body) => {
+                print_actions(body, script, recorder, config, depth + 1)?;
+                return Ok(false);
+            }
+            _ => recorder.record(|| parser::execute_action(action, config))?,
+        }
+    }
+    Ok(true)
 }
 
 fn main() {
     let args = Args::parse();
+    let mut config = Config {
+        layout: args.layout,
+        unicode: args.unicode,
+        unsupported: args.unsupported,
+        newline: args.newline,
+        carriage_return: args.carriage_return,
+        indent: args.indent,
+        speed: args.wpm.map_or_else(Speed::default, Speed::Wpm),
+        humanize: Humanize {
+            distribution: args.humanize,
+            ..Humanize::default()
+        },
+        typos: Typos {
+            rate: args.typo_rate,
+            ..Typos::default()
+        },
+        rng: args.seed.map_or_else(Rng::default, Rng::seeded),
+        ..Config::default()
+    };
+    for (name, value) in args.variables {
+        config.variables.set_override(&name, value);
+    }
 
-    // Get script content from either file or direct input
-    let script_content = if let Some(file_path) = args.file {
+    // Get script content from either file or direct input, along with a
+    // name to refer to it by in diagnostics
+    let (script_name, script_content) = if let Some(file_path) = args.file {
         match fs::read_to_string(&file_path) {
-            Ok(content) => content,
+            Ok(content) => (file_path.display().to_string(), content),
             Err(e) => {
                 eprintln!("Error reading file {:?}: {}", file_path, e);
                 process::exit(1);
             }
         }
     } else if let Some(script) = args.script {
-        script
+        ("<script>".to_string(), script)
     } else {
         eprintln!("Error: Either --file or --script must be provided");
         eprintln!("Use --help for more information");
         process::exit(1);
     };
 
+    // Report everything wrong with the script before any keys are sent
+    let problems = parser::check_script(&script_content, &config);
+    report_problems(&problems, &script_content, &script_name);
+
+    if args.check {
+        if problems.is_empty() {
+            println!("No problems found in {}", script_name);
+        }
+        return;
+    }
+
+    if args.dry_run {
+        if let Err(errors) = print_plan(&script_content, config) {
+            report_problems(&errors, &script_content, &script_name);
+        }
+        return;
+    }
+
+    install_release_guards();
+
     // Spawn a thread to run the script
     let worker_handle = thread::spawn(move || {
-        match parser::run_script(&script_content) {
+        match parser::run_script_with_config(&script_content, &config) {
             Ok(()) => {
                 println!("Script completed successfully.");
             }
-            Err(e) => {
-                eprintln!("Script error: {}", e);
-                process::exit(1);
+            Err(errors) => {
+                report_problems(&errors, &script_content, &script_name);
             }
         }
     });
@@ -64,7 +356,7 @@ fn main() {
         let callback = move |event: Event| {
             if let EventType::ButtonPress(Button::Left) = event.event_type {
                 println!("\nMouse click detected! Interrupting script...");
-                process::exit(0);
+                exit(0);
             }
         };
 
@@ -77,9 +369,11 @@ fn main() {
         });
     }
 
-    // Wait for the worker to finish
-    worker_handle.join().unwrap();
+    // Wait for the worker to finish; a panic has already been reported by the hook
+    if worker_handle.join().is_err() {
+        exit(101);
+    }
 
     // Worker completed successfully, exit cleanly
-    process::exit(0);
+    exit(0);
 }
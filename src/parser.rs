@@ -4,138 +4,571 @@
 //! allowing scripts to be loaded from files or command-line arguments instead of
 //! being compiled into the binary.
 
-use crate::typewriter::{sleep, type_chord, type_string};
+use crate::config::{
+    CarriageReturn, Config, Indent, Newline, Speed, UnicodeInput, Unsupported, Variables,
+};
+use crate::humanize::{Distribution, Rng};
+use crate::layout::Layout;
+use crate::typewriter::{
+    UnsupportedCharacter, hold_chord, now, press_chord, release_chord, release_held_keys, sleep,
+    type_chord, type_string,
+};
 use rdev::Key;
 use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
 
-#[derive(Debug)]
-pub enum ParseError {
+/// The kind of problem encountered while parsing a script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
     UnexpectedToken(String),
-    UnknownKey(String),
+    /// An unrecognised key name, with the closest known name if there is one.
+    UnknownKey(String, Option<String>),
     InvalidSyntax(String),
+    UnterminatedString,
+    InvalidEscape(String),
     UnexpectedEnd,
+    /// A character the keyboard layout in effect cannot type, with the layout's name.
+    UnsupportedCharacter(char, String),
+    /// A `${name}` reference to a variable that is not defined.
+    UnknownVariable(String),
 }
 
-impl fmt::Display for ParseError {
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::UnexpectedToken(t) => write!(f, "Unexpected token: {}", t),
+            ParseErrorKind::UnknownKey(k, Some(suggestion)) => {
+                write!(f, "Unknown key: {} (did you mean {}?)", k, suggestion)
+            }
+            ParseErrorKind::UnknownKey(k, None) => write!(f, "Unknown key: {}", k),
+            ParseErrorKind::InvalidSyntax(s) => write!(f, "Invalid syntax: {}", s),
+            ParseErrorKind::UnterminatedString => write!(f, "Unterminated string literal"),
+            ParseErrorKind::InvalidEscape(e) => write!(f, "Invalid escape sequence: {}", e),
+            ParseErrorKind::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            ParseErrorKind::UnsupportedCharacter(c, layout) => {
+                write!(f, "Cannot type {:?} with layout {}", c, layout)
+            }
+            ParseErrorKind::UnknownVariable(name) => write!(f, "Unknown variable: {}", name),
+        }
+    }
+}
+
+/// Whether a problem stops a script from running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            ParseError::UnexpectedToken(t) => write!(f, "Unexpected token: {}", t),
-            ParseError::UnknownKey(k) => write!(f, "Unknown key: {}", k),
-            ParseError::InvalidSyntax(s) => write!(f, "Invalid syntax: {}", s),
-            ParseError::UnexpectedEnd => write!(f, "Unexpected end of input"),
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// An error encountered while parsing a script, along with where it occurred.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub span: Span,
+    pub severity: Severity,
+}
+
+impl ParseError {
+    fn new(kind: ParseErrorKind, span: Span) -> Self {
+        Self {
+            kind,
+            span,
+            severity: Severity::Error,
         }
     }
+
+    /// Returns true if the problem stops the script from running.
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Renders the error as a compiler-style diagnostic.
+    ///
+    /// The output names the file, line and column of the error and shows
+    /// the offending source line with the problem underlined:
+    ///
+    /// ```text
+    /// error: Invalid syntax: Expected semicolon after number
+    ///  --> hello.tw:3:5
+    ///   |
+    /// 3 | 300 [Return];
+    ///   |     ^
+    /// ```
+    pub fn render(&self, source: &str, filename: &str) -> String {
+        let (line, column) = self.span.location(source);
+        let line_start = source[..self.span.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[self.span.start..]
+            .find('\n')
+            .map_or(source.len(), |i| self.span.start + i);
+        let text = source[line_start..line_end].trim_end_matches('\r');
+        let underline_end = self.span.end.clamp(self.span.start, line_end);
+        let width = source[self.span.start..underline_end]
+            .chars()
+            .count()
+            .max(1);
+        let gutter = " ".repeat(line.to_string().len());
+
+        format!(
+            "{}: {}\n{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}",
+            self.severity,
+            self.kind,
+            filename,
+            line,
+            column,
+            line,
+            text,
+            " ".repeat(column - 1),
+            "^".repeat(width),
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
 }
 
 impl std::error::Error for ParseError {}
 
+/// A byte range in the script source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Returns the 1-based line number on which this span starts.
+    pub fn line(&self, source: &str) -> usize {
+        self.location(source).0
+    }
+
+    /// Returns the 1-based line and column (in characters) at which this span starts.
+    pub fn location(&self, source: &str) -> (usize, usize) {
+        let before = &source[..self.start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+}
+
+/// A value paired with the location in the source it was parsed from.
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+/// A piece of a string literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    /// Text typed as written.
+    Text(String),
+    /// A `${name}` reference, replaced by the variable's value when the
+    /// script runs.
+    Var(String),
+}
+
+/// Joins the parts of a string literal back into text, writing variable
+/// references as `${name}`.
+fn template_text(parts: &[Part]) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Text(text) => text.clone(),
+            Part::Var(name) => format!("${{{}}}", name),
+        })
+        .collect()
+}
+
+/// Replaces the variable references in a string literal with their values.
+///
+/// # Errors
+///
+/// Returns the name of the first variable that is not defined.
+///
+/// # Examples
+///
+/// ```
+/// use typewriter::config::Variables;
+/// use typewriter::parser::{Part, interpolate};
+///
+/// let mut variables = Variables::default();
+/// variables.bind("name", "Ada".to_string());
+/// let parts = [Part::Text("Hello ".to_string()), Part::Var("name".to_string())];
+/// assert_eq!(interpolate(&parts, &variables).unwrap(), "Hello Ada");
+/// ```
+pub fn interpolate<'a>(parts: &'a [Part], variables: &Variables) -> Result<String, &'a str> {
+    let mut text = String::new();
+    for part in parts {
+        match part {
+            Part::Text(literal) => text.push_str(literal),
+            Part::Var(name) => text.push_str(variables.get(name).ok_or(name.as_str())?),
+        }
+    }
+    Ok(text)
+}
+
 #[derive(Debug, Clone)]
 enum Token {
     String(String),
+    /// A string literal containing at least one `${name}` reference.
+    Template(Vec<Part>),
     Number(u64),
+    /// A typing speed in words per minute, such as `90wpm`.
+    Wpm(u64),
+    /// A duration with units, such as `1m30s`, in milliseconds.
+    Duration(u64),
     LeftBracket,
     RightBracket,
     LeftParen,
     RightParen,
     Comma,
     Semicolon,
+    Plus,
+    Equals,
+    LeftBrace,
+    RightBrace,
     Identifier(String),
+    /// The text between the angle brackets of a chord literal like `<C-S-t>`.
+    Chord(String),
+}
+
+/// Returns the byte offset of the next unconsumed character.
+fn offset(chars: &mut Peekable<CharIndices>, input: &str) -> usize {
+    chars.peek().map_or(input.len(), |&(i, _)| i)
+}
+
+/// Returns the parts of a string literal token, or `None` for other tokens.
+fn string_parts(token: &Token) -> Option<Vec<Part>> {
+    match token {
+        Token::String(s) => Some(vec![Part::Text(s.clone())]),
+        Token::Template(parts) => Some(parts.clone()),
+        _ => None,
+    }
+}
+
+/// Parses a decimal number literal, which is either a bare number, a typing
+/// speed such as `90wpm` or a duration such as `300ms`, `1.5s` or `1m30s`.
+fn number_literal(text: &str) -> Result<Token, String> {
+    let number_end = |s: &str| {
+        s.find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(s.len())
+    };
+    let (number, unit) = text.split_at(number_end(text));
+    match unit {
+        "" if number.contains('.') => {
+            return Err(format!(
+                "Missing unit after {} (expected ms, s, m or h)",
+                number
+            ));
+        }
+        "" => {
+            return number
+                .parse()
+                .map(Token::Number)
+                .map_err(|_| format!("Invalid number: {}", text));
+        }
+        "wpm" => {
+            return match number.parse() {
+                Ok(0) => Err("Typing speed must be at least 1wpm".to_string()),
+                Ok(wpm) => Ok(Token::Wpm(wpm)),
+                Err(_) => Err(format!("Invalid typing speed: {}", text)),
+            };
+        }
+        _ => {}
+    }
+
+    // A duration made of one or more parts, each a number and a unit
+    let mut millis = 0.0;
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (number, tail) = rest.split_at(number_end(rest));
+        let unit_end = tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_end);
+        let Ok(number) = number.parse::<f64>() else {
+            return Err(format!("Invalid duration: {}", text));
+        };
+        let scale = match unit {
+            "ms" => 1.0,
+            "s" => 1_000.0,
+            "m" => 60_000.0,
+            "h" => 3_600_000.0,
+            "" => return Err(format!("Missing unit in duration {}", text)),
+            _ => {
+                return Err(format!(
+                    "Unknown unit {:?} (expected ms, s, m, h or wpm)",
+                    unit
+                ));
+            }
+        };
+        millis += number * scale;
+        rest = tail;
+    }
+    Ok(Token::Duration(millis.round() as u64))
+}
+
+/// Parses the body of a `\u{...}` escape, after the `u` has been consumed.
+///
+/// Returns `None` if the braces are missing, the digits are not 1 to 6
+/// hexadecimal characters, or the value is not a Unicode scalar value.
+fn unicode_escape(chars: &mut Peekable<CharIndices>) -> Option<char> {
+    chars.next_if(|&(_, c)| c == '{')?;
+    let mut digits = String::new();
+    while let Some((_, c)) = chars.next_if(|&(_, c)| c.is_ascii_hexdigit()) {
+        digits.push(c);
+    }
+    chars.next_if(|&(_, c)| c == '}')?;
+    if digits.is_empty() || digits.len() > 6 {
+        return None;
+    }
+    char::from_u32(u32::from_str_radix(&digits, 16).ok()?)
 }
 
 /// Tokenizes the input script into a stream of tokens
-fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+///
+/// Characters that cannot start a token are reported in `errors` and skipped,
+/// so a single stray character does not hide problems later in the script.
+fn tokenize(input: &str, errors: &mut Vec<ParseError>) -> Vec<Spanned<Token>> {
     let mut tokens = Vec::new();
-    let mut chars = input.chars().peekable();
+    let mut chars = input.char_indices().peekable();
 
-    while let Some(&ch) = chars.peek() {
-        match ch {
+    while let Some(&(start, ch)) = chars.peek() {
+        let token = match ch {
             // Skip whitespace
             ' ' | '\t' | '\n' | '\r' => {
                 chars.next();
+                continue;
             }
             // Skip comments
-            '/' if chars.clone().nth(1) == Some('/') => {
-                chars.next();
-                chars.next();
-                while let Some(&c) = chars.peek() {
-                    chars.next();
+            '/' if input[start..].starts_with("//") => {
+                for (_, c) in chars.by_ref() {
                     if c == '\n' {
                         break;
                     }
                 }
+                continue;
             }
             // String literals
             '"' => {
                 chars.next();
                 let mut string = String::new();
-                while let Some(&c) = chars.peek() {
-                    chars.next();
+                let mut parts = Vec::new();
+                let mut terminated = false;
+                while let Some((i, c)) = chars.next() {
                     if c == '"' {
+                        terminated = true;
                         break;
                     }
                     if c == '\\' {
-                        if let Some(&next) = chars.peek() {
-                            chars.next();
-                            match next {
-                                'n' => string.push('\n'),
-                                't' => string.push('\t'),
-                                'r' => string.push('\r'),
-                                '\\' => string.push('\\'),
-                                '"' => string.push('"'),
-                                _ => {
-                                    string.push('\\');
-                                    string.push(next);
-                                }
+                        let Some((_, next)) = chars.next() else {
+                            break;
+                        };
+                        let escaped = match next {
+                            'n' => Some('\n'),
+                            't' => Some('\t'),
+                            'r' => Some('\r'),
+                            '0' => Some('\0'),
+                            '\\' => Some('\\'),
+                            '"' => Some('"'),
+                            '\'' => Some('\''),
+                            '$' => Some('$'),
+                            'u' => unicode_escape(&mut chars),
+                            _ => None,
+                        };
+                        match escaped {
+                            Some(escaped) => string.push(escaped),
+                            None => {
+                                let end = offset(&mut chars, input);
+                                errors.push(ParseError::new(
+                                    ParseErrorKind::InvalidEscape(input[i..end].to_string()),
+                                    Span { start: i, end },
+                                ));
+                            }
+                        }
+                    } else if c == '$' && chars.next_if(|&(_, c)| c == '{').is_some() {
+                        // A ${name} reference to a variable
+                        let mut name = String::new();
+                        let mut closed = false;
+                        while let Some((_, c)) = chars.next_if(|&(_, c)| c != '"') {
+                            if c == '}' {
+                                closed = true;
+                                break;
                             }
+                            name.push(c);
+                        }
+                        let span = Span {
+                            start: i,
+                            end: offset(&mut chars, input),
+                        };
+                        if !closed {
+                            errors.push(ParseError::new(
+                                ParseErrorKind::InvalidSyntax(
+                                    "Unterminated variable reference".to_string(),
+                                ),
+                                span,
+                            ));
+                        } else if !Variables::is_valid_name(&name) {
+                            errors.push(ParseError::new(
+                                ParseErrorKind::InvalidSyntax(format!(
+                                    "Invalid variable name: {:?}",
+                                    name
+                                )),
+                                span,
+                            ));
+                        } else {
+                            if !string.is_empty() {
+                                parts.push(Part::Text(std::mem::take(&mut string)));
+                            }
+                            parts.push(Part::Var(name));
                         }
                     } else {
                         string.push(c);
                     }
                 }
-                tokens.push(Token::String(string));
+                if !terminated {
+                    errors.push(ParseError::new(
+                        ParseErrorKind::UnterminatedString,
+                        Span {
+                            start,
+                            end: input.len(),
+                        },
+                    ));
+                    continue;
+                }
+                if parts.is_empty() {
+                    Token::String(string)
+                } else {
+                    if !string.is_empty() {
+                        parts.push(Part::Text(string));
+                    }
+                    Token::Template(parts)
+                }
             }
-            // Numbers
+            // Numbers in hexadecimal with a 0x prefix
+            '0' if input[start..].starts_with("0x") => {
+                chars.next();
+                chars.next();
+                while chars.next_if(|&(_, c)| c.is_ascii_hexdigit()).is_some() {}
+                let end = offset(&mut chars, input);
+                match u64::from_str_radix(&input[start + 2..end], 16) {
+                    Ok(num) => Token::Number(num),
+                    Err(_) => {
+                        errors.push(ParseError::new(
+                            ParseErrorKind::InvalidSyntax(format!(
+                                "Invalid number: {}",
+                                &input[start..end]
+                            )),
+                            Span { start, end },
+                        ));
+                        continue;
+                    }
+                }
+            }
+            // Numbers in decimal, optionally followed by a unit, such as
+            // `500`, `90wpm`, `1m30s` or `1.5s`
             '0'..='9' => {
-                let mut num = String::new();
-                while let Some(&c) = chars.peek() {
-                    if c.is_ascii_digit() {
-                        num.push(c);
-                        chars.next();
-                    } else {
-                        break;
+                while chars
+                    .next_if(|&(_, c)| c.is_ascii_alphanumeric() || c == '.')
+                    .is_some()
+                {}
+                let end = offset(&mut chars, input);
+                match number_literal(&input[start..end]) {
+                    Ok(token) => token,
+                    Err(message) => {
+                        errors.push(ParseError::new(
+                            ParseErrorKind::InvalidSyntax(message),
+                            Span { start, end },
+                        ));
+                        continue;
                     }
                 }
-                tokens.push(Token::Number(num.parse().unwrap()));
             }
             // Single character tokens
             '[' => {
                 chars.next();
-                tokens.push(Token::LeftBracket);
+                Token::LeftBracket
             }
             ']' => {
                 chars.next();
-                tokens.push(Token::RightBracket);
+                Token::RightBracket
             }
             '(' => {
                 chars.next();
-                tokens.push(Token::LeftParen);
+                Token::LeftParen
             }
             ')' => {
                 chars.next();
-                tokens.push(Token::RightParen);
+                Token::RightParen
             }
             ',' => {
                 chars.next();
-                tokens.push(Token::Comma);
+                Token::Comma
             }
             ';' => {
                 chars.next();
-                tokens.push(Token::Semicolon);
+                Token::Semicolon
+            }
+            '+' => {
+                chars.next();
+                Token::Plus
+            }
+            '=' => {
+                chars.next();
+                Token::Equals
+            }
+            '{' => {
+                chars.next();
+                Token::LeftBrace
+            }
+            '}' => {
+                chars.next();
+                Token::RightBrace
+            }
+            // Chord literals such as <C-S-t>
+            '<' => {
+                chars.next();
+                let mut chord = String::new();
+                let mut terminated = false;
+                // A semicolon only ends the literal early if it is not the key,
+                // as in <C-;>, so a missing > does not swallow the next statement
+                while let Some((_, c)) = chars.next_if(|&(i, c)| {
+                    !c.is_whitespace() && (c != ';' || input[i + 1..].starts_with('>'))
+                }) {
+                    if c == '>' && !chord.is_empty() {
+                        terminated = true;
+                        break;
+                    }
+                    chord.push(c);
+                }
+                if !terminated {
+                    let end = offset(&mut chars, input);
+                    errors.push(ParseError::new(
+                        ParseErrorKind::InvalidSyntax("Unterminated chord literal".to_string()),
+                        Span { start, end },
+                    ));
+                    continue;
+                }
+                Token::Chord(chord)
             }
             // Identifiers (for Key names)
             'a'..='z' | 'A'..='Z' | '_' => {
                 let mut ident = String::new();
-                while let Some(&c) = chars.peek() {
+                while let Some(&(_, c)) = chars.peek() {
                     if c.is_alphanumeric() || c == '_' || c == ':' {
                         ident.push(c);
                         chars.next();
@@ -143,249 +576,1607 @@ fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
                         break;
                     }
                 }
-                tokens.push(Token::Identifier(ident));
+                Token::Identifier(ident)
             }
             _ => {
-                return Err(ParseError::UnexpectedToken(ch.to_string()));
+                chars.next();
+                errors.push(ParseError::new(
+                    ParseErrorKind::UnexpectedToken(ch.to_string()),
+                    Span {
+                        start,
+                        end: start + ch.len_utf8(),
+                    },
+                ));
+                continue;
             }
-        }
+        };
+        let end = offset(&mut chars, input);
+        tokens.push(Spanned {
+            node: token,
+            span: Span { start, end },
+        });
     }
 
-    Ok(tokens)
+    tokens
 }
 
+/// Names accepted for each key, canonical name first.
+///
+/// Names are matched case-insensitively.
+const KEY_NAMES: &[(&[&str], Key)] = &[
+    // Letters
+    (&["KeyA", "A"], Key::KeyA),
+    (&["KeyB", "B"], Key::KeyB),
+    (&["KeyC", "C"], Key::KeyC),
+    (&["KeyD", "D"], Key::KeyD),
+    (&["KeyE", "E"], Key::KeyE),
+    (&["KeyF", "F"], Key::KeyF),
+    (&["KeyG", "G"], Key::KeyG),
+    (&["KeyH", "H"], Key::KeyH),
+    (&["KeyI", "I"], Key::KeyI),
+    (&["KeyJ", "J"], Key::KeyJ),
+    (&["KeyK", "K"], Key::KeyK),
+    (&["KeyL", "L"], Key::KeyL),
+    (&["KeyM", "M"], Key::KeyM),
+    (&["KeyN", "N"], Key::KeyN),
+    (&["KeyO", "O"], Key::KeyO),
+    (&["KeyP", "P"], Key::KeyP),
+    (&["KeyQ", "Q"], Key::KeyQ),
+    (&["KeyR", "R"], Key::KeyR),
+    (&["KeyS", "S"], Key::KeyS),
+    (&["KeyT", "T"], Key::KeyT),
+    (&["KeyU", "U"], Key::KeyU),
+    (&["KeyV", "V"], Key::KeyV),
+    (&["KeyW", "W"], Key::KeyW),
+    (&["KeyX", "X"], Key::KeyX),
+    (&["KeyY", "Y"], Key::KeyY),
+    (&["KeyZ", "Z"], Key::KeyZ),
+    // Numbers
+    (&["Num0", "0"], Key::Num0),
+    (&["Num1", "1"], Key::Num1),
+    (&["Num2", "2"], Key::Num2),
+    (&["Num3", "3"], Key::Num3),
+    (&["Num4", "4"], Key::Num4),
+    (&["Num5", "5"], Key::Num5),
+    (&["Num6", "6"], Key::Num6),
+    (&["Num7", "7"], Key::Num7),
+    (&["Num8", "8"], Key::Num8),
+    (&["Num9", "9"], Key::Num9),
+    // Modifiers
+    (&["ControlLeft", "Ctrl", "Control"], Key::ControlLeft),
+    (&["ControlRight", "RightCtrl"], Key::ControlRight),
+    (&["ShiftLeft", "Shift"], Key::ShiftLeft),
+    (&["ShiftRight", "RightShift"], Key::ShiftRight),
+    (&["Alt", "AltLeft", "Opt", "Option"], Key::Alt),
+    (&["AltGr", "AltRight", "RightAlt"], Key::AltGr),
+    (
+        &[
+            "MetaLeft", "Meta", "Cmd", "Command", "Super", "Win", "Windows",
+        ],
+        Key::MetaLeft,
+    ),
+    (&["MetaRight", "RightMeta", "RightCmd"], Key::MetaRight),
+    // Special keys
+    (&["Return", "Enter", "CR"], Key::Return),
+    (&["Space"], Key::Space),
+    (&["Backspace", "BS"], Key::Backspace),
+    (&["Tab"], Key::Tab),
+    (&["Escape", "Esc"], Key::Escape),
+    (&["Delete", "Del"], Key::Delete),
+    (&["Home"], Key::Home),
+    (&["End"], Key::End),
+    (&["PageUp", "PgUp"], Key::PageUp),
+    (&["PageDown", "PgDn"], Key::PageDown),
+    (&["UpArrow", "Up"], Key::UpArrow),
+    (&["DownArrow", "Down"], Key::DownArrow),
+    (&["LeftArrow", "Left"], Key::LeftArrow),
+    (&["RightArrow", "Right"], Key::RightArrow),
+    (&["Insert", "Ins"], Key::Insert),
+    (&["CapsLock"], Key::CapsLock),
+    (&["PrintScreen"], Key::PrintScreen),
+    (&["ScrollLock"], Key::ScrollLock),
+    (&["Pause"], Key::Pause),
+    (&["NumLock"], Key::NumLock),
+    (&["Function", "Fn"], Key::Function),
+    // Punctuation
+    (&["BackQuote"], Key::BackQuote),
+    (&["Minus"], Key::Minus),
+    (&["Equal"], Key::Equal),
+    (&["LeftBracket"], Key::LeftBracket),
+    (&["RightBracket"], Key::RightBracket),
+    (&["SemiColon"], Key::SemiColon),
+    (&["Quote"], Key::Quote),
+    (&["BackSlash"], Key::BackSlash),
+    (&["IntlBackslash"], Key::IntlBackslash),
+    (&["Comma"], Key::Comma),
+    (&["Dot"], Key::Dot),
+    (&["Slash"], Key::Slash),
+    // Keypad
+    (&["Kp0"], Key::Kp0),
+    (&["Kp1"], Key::Kp1),
+    (&["Kp2"], Key::Kp2),
+    (&["Kp3"], Key::Kp3),
+    (&["Kp4"], Key::Kp4),
+    (&["Kp5"], Key::Kp5),
+    (&["Kp6"], Key::Kp6),
+    (&["Kp7"], Key::Kp7),
+    (&["Kp8"], Key::Kp8),
+    (&["Kp9"], Key::Kp9),
+    (&["KpReturn"], Key::KpReturn),
+    (&["KpMinus"], Key::KpMinus),
+    (&["KpPlus"], Key::KpPlus),
+    (&["KpMultiply"], Key::KpMultiply),
+    (&["KpDivide"], Key::KpDivide),
+    (&["KpDelete"], Key::KpDelete),
+    // Function keys
+    (&["F1"], Key::F1),
+    (&["F2"], Key::F2),
+    (&["F3"], Key::F3),
+    (&["F4"], Key::F4),
+    (&["F5"], Key::F5),
+    (&["F6"], Key::F6),
+    (&["F7"], Key::F7),
+    (&["F8"], Key::F8),
+    (&["F9"], Key::F9),
+    (&["F10"], Key::F10),
+    (&["F11"], Key::F11),
+    (&["F12"], Key::F12),
+];
+
 /// Parses a Key identifier into an rdev::Key
-fn parse_key(name: &str) -> Result<Key, ParseError> {
-    // Handle Key:: prefix
-    let name = name.strip_prefix("Key::").unwrap_or(name);
-
-    match name {
-        // Letters
-        "KeyA" | "A" => Ok(Key::KeyA),
-        "KeyB" | "B" => Ok(Key::KeyB),
-        "KeyC" | "C" => Ok(Key::KeyC),
-        "KeyD" | "D" => Ok(Key::KeyD),
-        "KeyE" | "E" => Ok(Key::KeyE),
-        "KeyF" | "F" => Ok(Key::KeyF),
-        "KeyG" | "G" => Ok(Key::KeyG),
-        "KeyH" | "H" => Ok(Key::KeyH),
-        "KeyI" | "I" => Ok(Key::KeyI),
-        "KeyJ" | "J" => Ok(Key::KeyJ),
-        "KeyK" | "K" => Ok(Key::KeyK),
-        "KeyL" | "L" => Ok(Key::KeyL),
-        "KeyM" | "M" => Ok(Key::KeyM),
-        "KeyN" | "N" => Ok(Key::KeyN),
-        "KeyO" | "O" => Ok(Key::KeyO),
-        "KeyP" | "P" => Ok(Key::KeyP),
-        "KeyQ" | "Q" => Ok(Key::KeyQ),
-        "KeyR" | "R" => Ok(Key::KeyR),
-        "KeyS" | "S" => Ok(Key::KeyS),
-        "KeyT" | "T" => Ok(Key::KeyT),
-        "KeyU" | "U" => Ok(Key::KeyU),
-        "KeyV" | "V" => Ok(Key::KeyV),
-        "KeyW" | "W" => Ok(Key::KeyW),
-        "KeyX" | "X" => Ok(Key::KeyX),
-        "KeyY" | "Y" => Ok(Key::KeyY),
-        "KeyZ" | "Z" => Ok(Key::KeyZ),
-
-        // Numbers
-        "Num0" | "0" => Ok(Key::Num0),
-        "Num1" | "1" => Ok(Key::Num1),
-        "Num2" | "2" => Ok(Key::Num2),
-        "Num3" | "3" => Ok(Key::Num3),
-        "Num4" | "4" => Ok(Key::Num4),
-        "Num5" | "5" => Ok(Key::Num5),
-        "Num6" | "6" => Ok(Key::Num6),
-        "Num7" | "7" => Ok(Key::Num7),
-        "Num8" | "8" => Ok(Key::Num8),
-        "Num9" | "9" => Ok(Key::Num9),
-
-        // Modifiers
-        "ControlLeft" | "Ctrl" | "Control" => Ok(Key::ControlLeft),
-        "ControlRight" | "RightCtrl" => Ok(Key::ControlRight),
-        "ShiftLeft" | "Shift" => Ok(Key::ShiftLeft),
-        "ShiftRight" | "RightShift" => Ok(Key::ShiftRight),
-        "Alt" | "AltLeft" => Ok(Key::Alt),
-        "AltRight" | "RightAlt" => Ok(Key::AltGr),
-        "MetaLeft" | "Meta" | "Cmd" | "Command" | "Super" => Ok(Key::MetaLeft),
-        "MetaRight" | "RightMeta" | "RightCmd" => Ok(Key::MetaRight),
-
-        // Special keys
-        "Return" | "Enter" => Ok(Key::Return),
-        "Space" => Ok(Key::Space),
-        "Backspace" => Ok(Key::Backspace),
-        "Tab" => Ok(Key::Tab),
-        "Escape" | "Esc" => Ok(Key::Escape),
-        "Delete" | "Del" => Ok(Key::Delete),
-        "Home" => Ok(Key::Home),
-        "End" => Ok(Key::End),
-        "PageUp" => Ok(Key::PageUp),
-        "PageDown" => Ok(Key::PageDown),
-        "UpArrow" | "Up" => Ok(Key::UpArrow),
-        "DownArrow" | "Down" => Ok(Key::DownArrow),
-        "LeftArrow" | "Left" => Ok(Key::LeftArrow),
-        "RightArrow" | "Right" => Ok(Key::RightArrow),
-
-        // Function keys
-        "F1" => Ok(Key::F1),
-        "F2" => Ok(Key::F2),
-        "F3" => Ok(Key::F3),
-        "F4" => Ok(Key::F4),
-        "F5" => Ok(Key::F5),
-        "F6" => Ok(Key::F6),
-        "F7" => Ok(Key::F7),
-        "F8" => Ok(Key::F8),
-        "F9" => Ok(Key::F9),
-        "F10" => Ok(Key::F10),
-        "F11" => Ok(Key::F11),
-        "F12" => Ok(Key::F12),
-
-        _ => Err(ParseError::UnknownKey(name.to_string())),
-    }
-}
-
-#[derive(Debug)]
-enum Action {
-    TypeString(String, u64),
+///
+/// Every `rdev::Key` variant is accepted by its canonical name, along with a
+/// few common aliases, ignoring case. Raw platform keycodes (`Key::Unknown`)
+/// take an argument and are handled by the parser instead.
+pub(crate) fn parse_key(name: &str) -> Result<Key, ParseErrorKind> {
+    let name = strip_key_prefix(name);
+
+    KEY_NAMES
+        .iter()
+        .find(|(names, _)| names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+        .map(|(_, key)| *key)
+        .ok_or_else(|| ParseErrorKind::UnknownKey(name.to_string(), suggest_key(name)))
+}
+
+/// Removes an optional `Key::` prefix from a key name.
+fn strip_key_prefix(name: &str) -> &str {
+    match name.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("Key::") => &name[5..],
+        _ => name,
+    }
+}
+
+/// Suggests the closest known key name to a misspelled one, if any is close enough.
+fn suggest_key(name: &str) -> Option<String> {
+    let name = name.to_ascii_lowercase();
+    KEY_NAMES
+        .iter()
+        .flat_map(|(names, _)| names.iter())
+        .map(|candidate| {
+            (
+                edit_distance(&name, &candidate.to_ascii_lowercase()),
+                candidate,
+            )
+        })
+        .filter(|(distance, candidate)| {
+            *distance <= (name.len() / 3).max(1) && *distance < candidate.len()
+        })
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+/// Parses the inside of a chord literal such as `<C-S-t>` into its keys.
+///
+/// Leading `X-` prefixes name modifiers, case-insensitively: `C` (Control),
+/// `S` (Shift), `A` or `M` (Alt), and `D` (Meta, i.e. Command or Super). The
+/// remainder is a key name or a single character such as `t`, `1` or `/`.
+fn parse_chord_literal(text: &str) -> Result<Vec<Key>, ParseErrorKind> {
+    let mut keys = Vec::new();
+    let mut rest = text;
+    while let Some((modifier, remainder)) = rest.split_once('-') {
+        if remainder.is_empty() {
+            break;
+        }
+        let key = match modifier.to_ascii_uppercase().as_str() {
+            "C" => Key::ControlLeft,
+            "S" => Key::ShiftLeft,
+            "A" | "M" => Key::Alt,
+            "D" => Key::MetaLeft,
+            _ => {
+                return Err(ParseErrorKind::InvalidSyntax(format!(
+                    "Unknown modifier {}- in chord literal",
+                    modifier
+                )));
+            }
+        };
+        keys.push(key);
+        rest = remainder;
+    }
+    let key = match rest {
+        "-" => Key::Minus,
+        "=" => Key::Equal,
+        "[" => Key::LeftBracket,
+        "]" => Key::RightBracket,
+        ";" => Key::SemiColon,
+        "'" => Key::Quote,
+        "\\" => Key::BackSlash,
+        "," => Key::Comma,
+        "." => Key::Dot,
+        "/" => Key::Slash,
+        "`" => Key::BackQuote,
+        name => parse_key(name)?,
+    };
+    keys.push(key);
+    Ok(keys)
+}
+
+/// Computes the Levenshtein distance between two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// A runtime setting changed by a `set` directive.
+#[derive(Debug, Clone)]
+pub enum Setting {
+    /// `set layout = "de";`
+    Layout(Layout),
+    /// `set unicode = "ibus";`
+    Unicode(UnicodeInput),
+    /// `set unsupported = "warn";`
+    Unsupported(Unsupported),
+    /// `set newline = "shift+return";`
+    Newline(Newline),
+    /// `set carriage_return = "newline";`
+    CarriageReturn(CarriageReturn),
+    /// `set indent = "strip";`
+    Indent(Indent),
+    /// `set humanize = "normal";`
+    Humanize(Distribution),
+    /// `set jitter = 30;`
+    Jitter(u64),
+    /// `set punctuation_pause = 200;`
+    PunctuationPause(u64),
+    /// `set whitespace_pause = 40;`
+    WhitespacePause(u64),
+    /// `set typo_rate = 5;`
+    TypoRate(u64),
+    /// `set typo_pause = 300;`
+    TypoPause(u64),
+    /// `set seed = 42;`
+    Seed(u64),
+    /// `set typing_delay = 30;` or `set typing_delay = 90wpm;`
+    TypingDelay(Speed),
+    /// `set key_delay = 10;`
+    KeyDelay(u64),
+}
+
+impl Setting {
+    /// Changes the corresponding field of `config`.
+    pub fn apply(&self, config: &mut Config) {
+        match self {
+            Setting::Layout(layout) => config.layout = layout.clone(),
+            Setting::Unicode(method) => config.unicode = *method,
+            Setting::Unsupported(policy) => config.unsupported = *policy,
+            Setting::Newline(newline) => config.newline = *newline,
+            Setting::CarriageReturn(carriage_return) => config.carriage_return = *carriage_return,
+            Setting::Indent(indent) => config.indent = *indent,
+            Setting::Humanize(distribution) => config.humanize.distribution = *distribution,
+            Setting::Jitter(millis) => config.humanize.jitter = *millis,
+            Setting::PunctuationPause(millis) => config.humanize.punctuation_pause = *millis,
+            Setting::WhitespacePause(millis) => config.humanize.whitespace_pause = *millis,
+            Setting::TypoRate(percent) => config.typos.rate = *percent,
+            Setting::TypoPause(millis) => config.typos.pause = *millis,
+            Setting::Seed(seed) => config.rng = Rng::seeded(*seed),
+            Setting::TypingDelay(speed) => config.speed = *speed,
+            Setting::KeyDelay(millis) => config.key_delay = *millis,
+        }
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Setting::Layout(layout) => write!(f, "layout = {:?}", layout.name()),
+            Setting::Unicode(method) => write!(f, "unicode = \"{}\"", method),
+            Setting::Unsupported(policy) => write!(f, "unsupported = \"{}\"", policy),
+            Setting::Newline(newline) => write!(f, "newline = \"{}\"", newline),
+            Setting::CarriageReturn(carriage_return) => {
+                write!(f, "carriage_return = \"{}\"", carriage_return)
+            }
+            Setting::Indent(indent) => write!(f, "indent = \"{}\"", indent),
+            Setting::Humanize(distribution) => write!(f, "humanize = \"{}\"", distribution),
+            Setting::Jitter(millis) => write!(f, "jitter = {}", millis),
+            Setting::PunctuationPause(millis) => write!(f, "punctuation_pause = {}", millis),
+            Setting::WhitespacePause(millis) => write!(f, "whitespace_pause = {}", millis),
+            Setting::TypoRate(percent) => write!(f, "typo_rate = {}", percent),
+            Setting::TypoPause(millis) => write!(f, "typo_pause = {}", millis),
+            Setting::Seed(seed) => write!(f, "seed = {}", seed),
+            Setting::TypingDelay(Speed::Millis(millis)) => write!(f, "typing_delay = {}", millis),
+            Setting::TypingDelay(Speed::Wpm(wpm)) => write!(f, "typing_delay = {}wpm", wpm),
+            Setting::KeyDelay(millis) => write!(f, "key_delay = {}", millis),
+        }
+    }
+}
+
+/// A single step of a parsed script.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// Type a string at the given speed, or at the configured speed if `None`.
+    TypeString(Vec<Part>, Option<Speed>),
+    /// Pause for the given number of milliseconds.
     Sleep(u64),
+    /// Press and release a key chord.
     TypeChord(Vec<Key>),
+    /// Press keys and leave them held down.
+    KeyDown(Vec<Key>),
+    /// Release keys that were held down.
+    KeyUp(Vec<Key>),
+    /// Press keys, hold them for the given number of milliseconds, then release them.
+    Hold(Vec<Key>, u64),
+    /// Change a setting for the rest of the script.
+    Set(Setting),
+    /// Bind a variable to a string for the rest of the script.
+    Let(String, Vec<Part>),
+    /// Run a block of actions the given number of times.
+    Repeat(u64, Vec<Spanned<Action>>),
+    /// Run a block of actions over and over, until the given number of
+    /// milliseconds have passed or forever if `None`. A timed loop also stops
+    /// after an iteration that takes no time.
+    Loop(Option<u64>, Vec<Spanned<Action>>),
 }
 
-/// Parses tokens into a list of actions
-fn parse_actions(tokens: &[Token]) -> Result<Vec<Action>, ParseError> {
-    let mut actions = Vec::new();
-    let mut i = 0;
+impl Action {
+    /// Returns true if running the action sends keys or sleeps, which is
+    /// what keeps a loop from spinning without doing anything.
+    fn takes_time(&self) -> bool {
+        match self {
+            Action::TypeString(parts, _) => parts
+                .iter()
+                .any(|part| !matches!(part, Part::Text(text) if text.is_empty())),
+            Action::Sleep(ms) => *ms > 0,
+            Action::TypeChord(_) | Action::KeyDown(_) | Action::KeyUp(_) | Action::Hold(..) => true,
+            Action::Set(_) | Action::Let(..) => false,
+            Action::Repeat(count, body) => *count > 0 && body.iter().any(|a| a.node.takes_time()),
+            Action::Loop(_, body) => body.iter().any(|a| a.node.takes_time()),
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::TypeString(parts, Some(speed)) => {
+                write!(f, "TypeString {:?} ({})", template_text(parts), speed)
+            }
+            Action::TypeString(parts, None) => write!(f, "TypeString {:?}", template_text(parts)),
+            Action::Sleep(ms) => write!(f, "Sleep {}ms", ms),
+            Action::TypeChord(keys) => write!(f, "TypeChord {:?}", keys),
+            Action::KeyDown(keys) => write!(f, "KeyDown {:?}", keys),
+            Action::KeyUp(keys) => write!(f, "KeyUp {:?}", keys),
+            Action::Hold(keys, ms) => write!(f, "Hold {:?} for {}ms", keys, ms),
+            Action::Set(setting) => write!(f, "Set {}", setting),
+            Action::Let(name, parts) => write!(f, "Let {} = {:?}", name, template_text(parts)),
+            Action::Repeat(1, _) => write!(f, "Repeat once"),
+            Action::Repeat(count, _) => write!(f, "Repeat {} times", count),
+            Action::Loop(Some(ms), _) => write!(f, "Loop for {}ms", ms),
+            Action::Loop(None, _) => write!(f, "Loop forever"),
+        }
+    }
+}
+
+/// A cursor over the token stream.
+struct Parser<'a> {
+    tokens: &'a [Spanned<Token>],
+    pos: usize,
+    /// Problems that do not prevent the rest of the statement from being parsed.
+    errors: Vec<ParseError>,
+}
+
+impl<'a> Parser<'a> {
+    fn new(tokens: &'a [Spanned<Token>]) -> Self {
+        Self {
+            tokens,
+            pos: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    fn peek(&self) -> Option<&'a Token> {
+        self.peek_at(0)
+    }
+
+    /// Returns the token `n` positions after the current one.
+    fn peek_at(&self, n: usize) -> Option<&'a Token> {
+        self.tokens.get(self.pos + n).map(|t| &t.node)
+    }
+
+    /// Span of the current token, or an empty span just past the last token.
+    fn span(&self) -> Span {
+        match self.tokens.get(self.pos) {
+            Some(token) => token.span,
+            None => {
+                let end = self.tokens.last().map_or(0, |t| t.span.end);
+                Span { start: end, end }
+            }
+        }
+    }
+
+    /// Span from the token at index `start` through the last consumed token.
+    fn span_from(&self, start: usize) -> Span {
+        Span {
+            start: self.tokens[start].span.start,
+            end: self.tokens[self.pos - 1].span.end,
+        }
+    }
+
+    fn error(&self, kind: ParseErrorKind) -> ParseError {
+        ParseError::new(kind, self.span())
+    }
+
+    fn syntax_error(&self, message: &str) -> ParseError {
+        self.error(ParseErrorKind::InvalidSyntax(message.to_string()))
+    }
+
+    /// Returns the current token, failing with `UnexpectedEnd` if there is none.
+    fn expect_token(&self) -> Result<&'a Token, ParseError> {
+        self.peek()
+            .ok_or_else(|| self.error(ParseErrorKind::UnexpectedEnd))
+    }
+
+    /// Consumes a semicolon, failing with `message` if the current token is anything else.
+    fn expect_semicolon(&mut self, message: &str) -> Result<(), ParseError> {
+        if let Some(Token::Semicolon) = self.peek() {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.syntax_error(message))
+        }
+    }
+
+    /// Skips past the next semicolon so parsing can resume after an error in
+    /// the statement starting at token `start`.
+    ///
+    /// Blocks are skipped as a whole, and skipping stops early at a closing
+    /// brace after the start of the statement, so the enclosing block is
+    /// still closed.
+    fn synchronize(&mut self, start: usize) {
+        let mut depth = 0;
+        while let Some(token) = self.peek() {
+            if let Token::RightBrace = token
+                && depth == 0
+                && self.pos > start
+            {
+                break;
+            }
+            self.pos += 1;
+            match token {
+                Token::LeftBrace => depth += 1,
+                Token::RightBrace if depth > 1 => depth -= 1,
+                Token::RightBrace => {
+                    if let Some(Token::Semicolon) = self.peek() {
+                        self.pos += 1;
+                    }
+                    break;
+                }
+                Token::Semicolon if depth == 0 => break,
+                _ => {}
+            }
+        }
+    }
+
+    /// Parses statements until the end of the input, or until a closing brace
+    /// if `in_block`, recovering from errors in individual statements.
+    fn parse_statements(&mut self, in_block: bool) -> Vec<Spanned<Action>> {
+        let mut actions = Vec::new();
+        while let Some(token) = self.peek() {
+            if in_block && matches!(token, Token::RightBrace) {
+                break;
+            }
+            let start = self.pos;
+            match self.parse_statement() {
+                Ok(statement) => {
+                    let span = self.span_from(start);
+                    actions.extend(statement.into_iter().map(|node| Spanned { node, span }));
+                }
+                Err(error) => {
+                    self.errors.push(error);
+                    self.synchronize(start);
+                }
+            }
+        }
+        actions
+    }
 
-    while i < tokens.len() {
-        match &tokens[i] {
+    /// Parses a block of statements in braces, followed by an optional semicolon.
+    fn parse_block(&mut self) -> Result<Vec<Spanned<Action>>, ParseError> {
+        let Token::LeftBrace = self.expect_token()? else {
+            return Err(self.syntax_error("Expected { to start block"));
+        };
+        let open = self.span();
+        self.pos += 1;
+        let body = self.parse_statements(true);
+        if self.at_end() {
+            return Err(ParseError::new(
+                ParseErrorKind::InvalidSyntax("Unclosed block".to_string()),
+                open,
+            ));
+        }
+        self.pos += 1;
+        if let Some(Token::Semicolon) = self.peek() {
+            self.pos += 1;
+        }
+        Ok(body)
+    }
+
+    /// Parses the next statement into zero or more actions.
+    fn parse_statement(&mut self) -> Result<Vec<Action>, ParseError> {
+        let action = match self.expect_token()? {
             // String literal
-            Token::String(s) => {
-                i += 1;
+            token @ (Token::String(_) | Token::Template(_)) => {
+                let parts = string_parts(token).unwrap_or_default();
+                self.pos += 1;
                 // Check if followed by semicolon or part of tuple
-                if i < tokens.len() {
-                    match &tokens[i] {
-                        Token::Semicolon => {
-                            actions.push(Action::TypeString(s.clone(), 50));
-                            i += 1;
-                        }
-                        Token::Comma => {
-                            return Err(ParseError::InvalidSyntax(
-                                "String followed by comma - use parentheses for tuple: (\"text\", delay)".to_string()
-                            ));
-                        }
-                        _ => {
-                            return Err(ParseError::UnexpectedToken(format!("{:?}", tokens[i])));
-                        }
+                match self.expect_token()? {
+                    Token::Semicolon => {
+                        self.pos += 1;
+                        Action::TypeString(parts, None)
+                    }
+                    Token::Comma => {
+                        return Err(self.syntax_error(
+                            "String followed by comma - use parentheses for tuple: (\"text\", delay)",
+                        ));
+                    }
+                    other => {
+                        return Err(
+                            self.error(ParseErrorKind::UnexpectedToken(format!("{:?}", other)))
+                        );
                     }
-                } else {
-                    return Err(ParseError::UnexpectedEnd);
                 }
             }
             // Tuple (string, delay)
             Token::LeftParen => {
-                i += 1;
-                if let Token::String(s) = &tokens.get(i).ok_or(ParseError::UnexpectedEnd)? {
-                    let string = s.clone();
-                    i += 1;
-                    if let Token::Comma = tokens.get(i).ok_or(ParseError::UnexpectedEnd)? {
-                        i += 1;
-                        if let Token::Number(delay) = tokens.get(i).ok_or(ParseError::UnexpectedEnd)? {
-                            let delay = *delay;
-                            i += 1;
-                            if let Token::RightParen = tokens.get(i).ok_or(ParseError::UnexpectedEnd)? {
-                                i += 1;
-                                if let Token::Semicolon = tokens.get(i).ok_or(ParseError::UnexpectedEnd)? {
-                                    i += 1;
-                                    actions.push(Action::TypeString(string, delay));
-                                } else {
-                                    return Err(ParseError::InvalidSyntax("Expected semicolon after tuple".to_string()));
-                                }
-                            } else {
-                                return Err(ParseError::InvalidSyntax("Expected ) after delay".to_string()));
-                            }
-                        } else {
-                            return Err(ParseError::InvalidSyntax("Expected number for delay".to_string()));
-                        }
-                    } else {
-                        return Err(ParseError::InvalidSyntax("Expected comma in tuple".to_string()));
-                    }
-                } else {
-                    return Err(ParseError::InvalidSyntax("Expected string in tuple".to_string()));
+                self.pos += 1;
+                let Some(parts) = string_parts(self.expect_token()?) else {
+                    return Err(self.syntax_error("Expected string in tuple"));
+                };
+                self.pos += 1;
+                let Token::Comma = self.expect_token()? else {
+                    return Err(self.syntax_error("Expected comma in tuple"));
+                };
+                self.pos += 1;
+                let speed = match self.expect_token()? {
+                    Token::Number(delay) | Token::Duration(delay) => Speed::Millis(*delay),
+                    Token::Wpm(wpm) => Speed::Wpm(*wpm),
+                    _ => return Err(self.syntax_error("Expected duration or wpm for delay")),
+                };
+                self.pos += 1;
+                let Token::RightParen = self.expect_token()? else {
+                    return Err(self.syntax_error("Expected ) after delay"));
+                };
+                self.pos += 1;
+                // A tuple at the very end of the script is reported as `UnexpectedEnd`
+                self.expect_token()?;
+                self.expect_semicolon("Expected semicolon after tuple")?;
+                Action::TypeString(parts, Some(speed))
+            }
+            // Number or duration (sleep)
+            Token::Number(n) | Token::Duration(n) => {
+                self.pos += 1;
+                self.expect_semicolon("Expected semicolon after duration")?;
+                Action::Sleep(*n)
+            }
+            // Array (key chord)
+            Token::LeftBracket => {
+                self.pos += 1;
+                let keys = self.parse_key_array()?;
+                self.expect_semicolon("Expected semicolon after key array")?;
+                Action::TypeChord(keys)
+            }
+            // Variable bindings (let name = "value";)
+            Token::Identifier(keyword) if keyword == "let" => {
+                self.pos += 1;
+                let Token::Identifier(name) = self.expect_token()? else {
+                    return Err(self.syntax_error("Expected variable name after let"));
+                };
+                if !Variables::is_valid_name(name) {
+                    return Err(self.syntax_error(&format!("Invalid variable name: {:?}", name)));
                 }
+                self.pos += 1;
+                let Token::Equals = self.expect_token()? else {
+                    return Err(self.syntax_error("Expected = after variable name"));
+                };
+                self.pos += 1;
+                let Some(parts) = string_parts(self.expect_token()?) else {
+                    return Err(self.syntax_error(&format!("Expected string for {}", name)));
+                };
+                self.pos += 1;
+                // A binding at the very end of the script is reported as `UnexpectedEnd`
+                self.expect_token()?;
+                self.expect_semicolon("Expected semicolon after variable binding")?;
+                Action::Let(name.clone(), parts)
+            }
+            // Blocks (repeat 5 { .. } and loop { .. } or loop for 30s { .. })
+            Token::Identifier(keyword) if keyword == "repeat" => {
+                self.pos += 1;
+                let Token::Number(count) = self.expect_token()? else {
+                    return Err(self.syntax_error("Expected number of repetitions"));
+                };
+                self.pos += 1;
+                Action::Repeat(*count, self.parse_block()?)
             }
-            // Number (sleep)
-            Token::Number(n) => {
-                i += 1;
-                if let Some(Token::Semicolon) = tokens.get(i) {
-                    i += 1;
-                    actions.push(Action::Sleep(*n));
+            Token::Identifier(keyword) if keyword == "loop" => {
+                let keyword_span = self.span();
+                self.pos += 1;
+                let limit = match self.expect_token()? {
+                    Token::Identifier(word) if word == "for" => {
+                        self.pos += 1;
+                        let (Token::Number(ms) | Token::Duration(ms)) = self.expect_token()? else {
+                            return Err(self.syntax_error("Expected duration for loop"));
+                        };
+                        self.pos += 1;
+                        Some(*ms)
+                    }
+                    Token::LeftBrace => None,
+                    _ => return Err(self.syntax_error("Expected { or `for` after loop")),
+                };
+                let body = self.parse_block()?;
+                // A body that neither types nor sleeps would spin forever
+                let problem = if body.is_empty() {
+                    Some("Loop body is empty")
+                } else if !body.iter().any(|a| a.node.takes_time()) {
+                    Some("Loop body never types or sleeps")
                 } else {
-                    return Err(ParseError::InvalidSyntax("Expected semicolon after number".to_string()));
+                    None
+                };
+                if let Some(problem) = problem {
+                    self.errors.push(ParseError::new(
+                        ParseErrorKind::InvalidSyntax(problem.to_string()),
+                        keyword_span,
+                    ));
                 }
+                Action::Loop(limit, body)
             }
-            // Array (key chord)
-            Token::LeftBracket => {
-                i += 1;
-                let mut keys = Vec::new();
-                loop {
-                    match tokens.get(i).ok_or(ParseError::UnexpectedEnd)? {
-                        Token::Identifier(key_name) => {
-                            keys.push(parse_key(key_name)?);
-                            i += 1;
-                            match tokens.get(i).ok_or(ParseError::UnexpectedEnd)? {
-                                Token::Comma => {
-                                    i += 1;
-                                    continue;
-                                }
-                                Token::RightBracket => {
-                                    i += 1;
-                                    break;
-                                }
-                                _ => return Err(ParseError::InvalidSyntax("Expected comma or ] in key array".to_string())),
-                            }
-                        }
-                        Token::RightBracket => {
-                            i += 1;
-                            break;
+            // Held keys (down [..]; up [..]; hold [..] for 800;)
+            Token::Identifier(keyword)
+                if matches!(keyword.as_str(), "down" | "up" | "hold")
+                    && matches!(self.peek_at(1), Some(Token::LeftBracket)) =>
+            {
+                self.pos += 2;
+                let keys = self.parse_key_array()?;
+                match keyword.as_str() {
+                    "down" => {
+                        self.expect_semicolon("Expected semicolon after key array")?;
+                        Action::KeyDown(keys)
+                    }
+                    "up" => {
+                        self.expect_semicolon("Expected semicolon after key array")?;
+                        Action::KeyUp(keys)
+                    }
+                    _ => {
+                        let Some(Token::Identifier(word)) = self.peek() else {
+                            return Err(self.syntax_error("Expected `for` after held keys"));
+                        };
+                        if word != "for" {
+                            return Err(self.syntax_error("Expected `for` after held keys"));
                         }
-                        _ => return Err(ParseError::InvalidSyntax("Expected key identifier in array".to_string())),
+                        self.pos += 1;
+                        let (Token::Number(ms) | Token::Duration(ms)) = self.expect_token()? else {
+                            return Err(self.syntax_error("Expected duration for hold"));
+                        };
+                        self.pos += 1;
+                        self.expect_semicolon("Expected semicolon after hold duration")?;
+                        Action::Hold(keys, *ms)
                     }
                 }
-                if let Some(Token::Semicolon) = tokens.get(i) {
-                    i += 1;
-                    actions.push(Action::TypeChord(keys));
-                } else {
-                    return Err(ParseError::InvalidSyntax("Expected semicolon after key array".to_string()));
-                }
+            }
+            // Settings (set layout = "de";)
+            Token::Identifier(keyword)
+                if keyword == "set" && matches!(self.peek_at(1), Some(Token::Identifier(_))) =>
+            {
+                self.pos += 1;
+                let setting = self.parse_setting()?;
+                self.expect_semicolon("Expected semicolon after setting")?;
+                Action::Set(setting)
+            }
+            // Chord shorthand (<C-x> <C-s> or Ctrl+Shift+T)
+            Token::Chord(_) => return self.parse_chord_sequence(),
+            Token::Identifier(_) if matches!(self.peek_at(1), Some(Token::Plus)) => {
+                return self.parse_chord_sequence();
             }
             Token::Semicolon => {
                 // Skip extra semicolons
-                i += 1;
+                self.pos += 1;
+                return Ok(Vec::new());
+            }
+            other => {
+                return Err(self.error(ParseErrorKind::UnexpectedToken(format!("{:?}", other))));
+            }
+        };
+        Ok(vec![action])
+    }
+
+    /// Parses `name = value` after the `set` keyword.
+    fn parse_setting(&mut self) -> Result<Setting, ParseError> {
+        let Some(Token::Identifier(name)) = self.peek() else {
+            return Err(self.syntax_error("Expected setting name"));
+        };
+        let name_span = self.span();
+        self.pos += 1;
+        let Token::Equals = self.expect_token()? else {
+            return Err(self.syntax_error("Expected = after setting name"));
+        };
+        self.pos += 1;
+        let value = self.expect_token()?;
+        let setting = match (name.as_str(), value) {
+            ("layout", Token::String(value)) => Layout::resolve(value).map(Setting::Layout),
+            ("unicode", Token::String(value)) => UnicodeInput::by_name(value).map(Setting::Unicode),
+            ("unsupported", Token::String(value)) => {
+                Unsupported::by_name(value).map(Setting::Unsupported)
+            }
+            ("newline", Token::String(value)) => Newline::by_name(value).map(Setting::Newline),
+            ("carriage_return", Token::String(value)) => {
+                CarriageReturn::by_name(value).map(Setting::CarriageReturn)
+            }
+            ("indent", Token::String(value)) => Indent::by_name(value).map(Setting::Indent),
+            ("humanize", Token::String(value)) => {
+                Distribution::by_name(value).map(Setting::Humanize)
+            }
+            (
+                "layout" | "unicode" | "unsupported" | "newline" | "carriage_return" | "indent"
+                | "humanize",
+                Token::Template(_),
+            ) => Err("Variables cannot be used in settings".to_string()),
+            (
+                "layout" | "unicode" | "unsupported" | "newline" | "carriage_return" | "indent"
+                | "humanize",
+                _,
+            ) => {
+                return Err(self.syntax_error(&format!("Expected string for {}", name)));
+            }
+            ("typo_rate", Token::Number(n)) if *n <= 100 => Ok(Setting::TypoRate(*n)),
+            ("typo_rate", Token::Number(_)) => {
+                Err("Typo rate must be a percentage from 0 to 100".to_string())
+            }
+            ("seed", Token::Number(n)) => Ok(Setting::Seed(*n)),
+            ("typo_rate" | "seed", _) => {
+                return Err(self.syntax_error(&format!("Expected number for {}", name)));
+            }
+            ("jitter", Token::Number(n) | Token::Duration(n)) => Ok(Setting::Jitter(*n)),
+            ("punctuation_pause", Token::Number(n) | Token::Duration(n)) => {
+                Ok(Setting::PunctuationPause(*n))
+            }
+            ("whitespace_pause", Token::Number(n) | Token::Duration(n)) => {
+                Ok(Setting::WhitespacePause(*n))
+            }
+            ("typo_pause", Token::Number(n) | Token::Duration(n)) => Ok(Setting::TypoPause(*n)),
+            ("key_delay", Token::Number(n) | Token::Duration(n)) => Ok(Setting::KeyDelay(*n)),
+            (
+                "jitter" | "punctuation_pause" | "whitespace_pause" | "typo_pause" | "key_delay",
+                _,
+            ) => {
+                return Err(self.syntax_error(&format!("Expected duration for {}", name)));
+            }
+            ("typing_delay", Token::Number(n) | Token::Duration(n)) => {
+                Ok(Setting::TypingDelay(Speed::Millis(*n)))
+            }
+            ("typing_delay", Token::Wpm(wpm)) => Ok(Setting::TypingDelay(Speed::Wpm(*wpm))),
+            ("typing_delay", _) => {
+                return Err(self.syntax_error("Expected duration or wpm for typing_delay"));
             }
             _ => {
-                return Err(ParseError::UnexpectedToken(format!("{:?}", tokens[i])));
+                return Err(ParseError::new(
+                    ParseErrorKind::InvalidSyntax(format!("Unknown setting: {}", name)),
+                    name_span,
+                ));
+            }
+        };
+        let setting = setting.map_err(|err| self.syntax_error(&err))?;
+        self.pos += 1;
+        Ok(setting)
+    }
+
+    /// Parses one or more shorthand chords up to and including the semicolon.
+    ///
+    /// Each chord is either a literal like `<C-S-t>` or keys joined with `+`
+    /// like `Ctrl+Shift+T`, and becomes a separate `TypeChord` action.
+    fn parse_chord_sequence(&mut self) -> Result<Vec<Action>, ParseError> {
+        let mut actions = Vec::new();
+        loop {
+            match self.expect_token()? {
+                Token::Chord(text) => {
+                    match parse_chord_literal(text) {
+                        Ok(keys) => actions.push(Action::TypeChord(keys)),
+                        Err(kind) => self.errors.push(self.error(kind)),
+                    }
+                    self.pos += 1;
+                }
+                Token::Identifier(_) | Token::Number(_) => {
+                    actions.push(Action::TypeChord(self.parse_plus_chord()?));
+                }
+                Token::Semicolon => {
+                    self.pos += 1;
+                    return Ok(actions);
+                }
+                _ => return Err(self.syntax_error("Expected chord or semicolon")),
+            }
+        }
+    }
+
+    /// Parses keys joined with `+`, such as `Ctrl+Shift+T`.
+    fn parse_plus_chord(&mut self) -> Result<Vec<Key>, ParseError> {
+        let mut keys = Vec::new();
+        loop {
+            let name = match self.expect_token()? {
+                Token::Identifier(name) => name.clone(),
+                Token::Number(n) => n.to_string(),
+                _ => return Err(self.syntax_error("Expected key after +")),
+            };
+            match parse_key(&name) {
+                Ok(key) => keys.push(key),
+                Err(kind) => self.errors.push(self.error(kind)),
+            }
+            self.pos += 1;
+            if let Some(Token::Plus) = self.peek() {
+                self.pos += 1;
+            } else {
+                return Ok(keys);
             }
         }
     }
 
-    Ok(actions)
+    /// Parses a raw platform keycode such as `Raw(0x2F)` into `Key::Unknown`.
+    ///
+    /// `Unknown(..)` is accepted as well, matching the `rdev::Key` variant name.
+    fn parse_raw_key(&mut self, name: &str) -> Result<Key, ParseError> {
+        let name = strip_key_prefix(name);
+        if !name.eq_ignore_ascii_case("Raw") && !name.eq_ignore_ascii_case("Unknown") {
+            return Err(self.error(ParseErrorKind::UnknownKey(name.to_string(), None)));
+        }
+        self.pos += 2;
+        let Token::Number(code) = self.expect_token()? else {
+            return Err(self.syntax_error("Expected keycode in Raw(..)"));
+        };
+        let code = u32::try_from(*code).map_err(|_| self.syntax_error("Keycode out of range"))?;
+        self.pos += 1;
+        let Token::RightParen = self.expect_token()? else {
+            return Err(self.syntax_error("Expected ) after keycode"));
+        };
+        self.pos += 1;
+        Ok(Key::Unknown(code))
+    }
+
+    /// Parses the keys of a chord up to and including the closing `]`.
+    fn parse_key_array(&mut self) -> Result<Vec<Key>, ParseError> {
+        let mut keys = Vec::new();
+        loop {
+            let key_name = match self.expect_token()? {
+                Token::Identifier(key_name) => key_name.clone(),
+                // Digit keys such as the 1 in [Ctrl, 1] are lexed as numbers
+                Token::Number(n) => n.to_string(),
+                Token::RightBracket => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => return Err(self.syntax_error("Expected key identifier in array")),
+            };
+            if let Some(Token::LeftParen) = self.peek_at(1) {
+                keys.push(self.parse_raw_key(&key_name)?);
+            } else {
+                // Unknown keys are recorded but do not stop parsing, so every
+                // misspelled key in the chord is reported at once
+                match parse_key(&key_name) {
+                    Ok(key) => keys.push(key),
+                    Err(kind) => self.errors.push(self.error(kind)),
+                }
+                self.pos += 1;
+            }
+            match self.expect_token()? {
+                Token::Comma => self.pos += 1,
+                Token::RightBracket => {
+                    self.pos += 1;
+                    break;
+                }
+                _ => return Err(self.syntax_error("Expected comma or ] in key array")),
+            }
+        }
+        Ok(keys)
+    }
 }
 
-/// Executes a list of actions
-fn execute_actions(actions: &[Action]) {
+/// Parses tokens into a list of actions
+///
+/// After an error the parser resynchronises at the next semicolon, so every
+/// problem in the script is reported in `errors` rather than just the first.
+fn parse_actions(tokens: &[Spanned<Token>], errors: &mut Vec<ParseError>) -> Vec<Spanned<Action>> {
+    let mut parser = Parser::new(tokens);
+    let actions = parser.parse_statements(false);
+    errors.append(&mut parser.errors);
+    actions
+}
+
+/// Replaces the variable references in the string literal of `action` with
+/// their values in `config`.
+///
+/// # Errors
+///
+/// Returns an error at the action's location if a variable is not defined.
+fn expand(parts: &[Part], action: &Spanned<Action>, config: &Config) -> Result<String, ParseError> {
+    interpolate(parts, &config.variables).map_err(|name| {
+        ParseError::new(
+            ParseErrorKind::UnknownVariable(name.to_string()),
+            action.span,
+        )
+    })
+}
+
+/// Executes a single action
+///
+/// `set` directives update `config`, which applies to every later action.
+///
+/// # Errors
+///
+/// Returns an error at the action's location if it refers to an undefined
+/// variable, or types a character that the configuration in effect cannot
+/// type under the [`Unsupported::Error`] policy. Scripts are checked for
+/// both before they run, see [`check_script`].
+pub fn execute_action(action: &Spanned<Action>, config: &mut Config) -> Result<(), ParseError> {
+    let unsupported = |err: UnsupportedCharacter| {
+        ParseError::new(
+            ParseErrorKind::UnsupportedCharacter(err.character, err.layout),
+            action.span,
+        )
+    };
+    match &action.node {
+        Action::TypeString(parts, speed) => {
+            let delay = speed.unwrap_or(config.speed).char_delay(config.key_delay);
+            type_string(expand(parts, action, config)?, delay, config).map_err(unsupported)?
+        }
+        Action::Sleep(ms) => sleep(*ms),
+        Action::TypeChord(keys) => type_chord(keys.clone(), config),
+        Action::KeyDown(keys) => press_chord(keys.clone(), config),
+        Action::KeyUp(keys) => release_chord(keys.clone(), config),
+        Action::Hold(keys, ms) => hold_chord(keys.clone(), *ms, config),
+        Action::Set(setting) => setting.apply(config),
+        Action::Let(name, parts) => {
+            let value = expand(parts, action, config)?;
+            config.variables.bind(name, value)
+        }
+        Action::Repeat(count, body) => {
+            for _ in 0..*count {
+                execute_actions(body, config)?;
+            }
+        }
+        Action::Loop(Some(ms), body) => {
+            let start = now();
+            while now() - start < *ms {
+                let before = now();
+                execute_actions(body, config)?;
+                // An iteration that takes no time would never end the loop
+                // on a backend with a virtual clock
+                if now() == before {
+                    break;
+                }
+            }
+        }
+        Action::Loop(None, body) => loop {
+            execute_actions(body, config)?;
+        },
+    }
+    Ok(())
+}
+
+/// Executes a list of actions in order
+///
+/// `set` directives update `config`, which applies to every later action.
+///
+/// # Errors
+///
+/// Stops at the first action that fails, see [`execute_action`].
+///
+/// # Examples
+///
+/// ```
+/// use typewriter::backend::Recorder;
+/// use typewriter::config::Config;
+/// use typewriter::parser::{execute_actions, parse_script};
+///
+/// let actions = parse_script("repeat 3 { 100ms; }").unwrap();
+/// let recorder = Recorder::new();
+/// recorder.record(|| execute_actions(&actions, &mut Config::default())).unwrap();
+/// assert_eq!(recorder.elapsed(), 300);
+/// ```
+pub fn execute_actions(actions: &[Spanned<Action>], config: &mut Config) -> Result<(), ParseError> {
     for action in actions {
-        match action {
-            Action::TypeString(s, delay) => type_string(s.clone(), *delay),
-            Action::Sleep(ms) => sleep(*ms),
-            Action::TypeChord(keys) => type_chord(keys.clone()),
+        execute_action(action, config)?;
+    }
+    Ok(())
+}
+
+/// Parses a typewriter script into a list of actions without executing it
+///
+/// # Errors
+///
+/// Returns every problem found in the script, ordered by position.
+pub fn parse_script(script: &str) -> Result<Vec<Spanned<Action>>, Vec<ParseError>> {
+    let mut errors = Vec::new();
+    let tokens = tokenize(script, &mut errors);
+    let actions = parse_actions(&tokens, &mut errors);
+
+    if errors.is_empty() {
+        Ok(actions)
+    } else {
+        errors.sort_by_key(|e| e.span.start);
+        Err(errors)
+    }
+}
+
+/// Returns the span of the `occurrence`-th (0-based) `pattern` within
+/// `span`, or all of `span` if it does not appear literally, for example
+/// because a character was written as an escape sequence.
+fn locate(script: &str, span: Span, pattern: &str, occurrence: usize) -> Span {
+    script[span.start..span.end]
+        .match_indices(pattern)
+        .nth(occurrence)
+        .map_or(span, |(i, _)| Span {
+            start: span.start + i,
+            end: span.start + i + pattern.len(),
+        })
+}
+
+/// Counts how often `item` has been seen before, and records one more.
+fn occurrence<T: PartialEq>(seen: &mut Vec<(T, usize)>, item: T) -> usize {
+    match seen.iter_mut().find(|(seen, _)| *seen == item) {
+        Some((_, count)) => {
+            *count += 1;
+            *count - 1
+        }
+        None => {
+            seen.push((item, 1));
+            0
+        }
+    }
+}
+
+/// Finds problems in the script's strings that only show up with the
+/// configuration in effect: references to undefined variables, and
+/// characters that can be typed neither with the keyboard layout nor the
+/// Unicode input method.
+///
+/// `set` directives and `let` bindings are applied as they are encountered,
+/// starting from `config`. Characters are reported as errors or warnings
+/// according to the `unsupported` policy in effect; skipped and substituted
+/// characters are not reported. The body of a block that can run more than
+/// once is checked twice, so that settings changed at the end of the body
+/// are taken into account at its start. Later iterations assign the same
+/// settings again, so they cannot find anything new.
+fn check_characters(script: &str, actions: &[Spanned<Action>], config: &Config) -> Vec<ParseError> {
+    let mut problems = Vec::new();
+    check_actions(script, actions, &mut config.clone(), &mut problems);
+    problems.sort_by_key(|problem| problem.span.start);
+    problems
+}
+
+/// Adds the problems in `actions` to `problems`, see [`check_characters`].
+fn check_actions(
+    script: &str,
+    actions: &[Spanned<Action>],
+    config: &mut Config,
+    problems: &mut Vec<ParseError>,
+) {
+    for action in actions {
+        match &action.node {
+            Action::Set(setting) => setting.apply(config),
+            Action::Let(name, parts) => match interpolate(parts, &config.variables) {
+                Ok(value) => config.variables.bind(name, value),
+                Err(unknown) => problems.push(ParseError::new(
+                    ParseErrorKind::UnknownVariable(unknown.to_string()),
+                    locate(script, action.span, &format!("${{{}}}", unknown), 0),
+                )),
+            },
+            Action::Repeat(count, body) => {
+                check_block(script, body, (*count).clamp(1, 2), config, problems)
+            }
+            Action::Loop(_, body) => check_block(script, body, 2, config, problems),
+            Action::TypeString(parts, _) => {
+                let severity = match config.unsupported {
+                    Unsupported::Error => Some(Severity::Error),
+                    Unsupported::Warn => Some(Severity::Warning),
+                    Unsupported::Skip | Unsupported::Fallback => None,
+                };
+                let mut seen_chars = Vec::new();
+                let mut seen_vars = Vec::new();
+                for part in parts {
+                    match part {
+                        Part::Text(text) => {
+                            for c in text.chars() {
+                                let occurrence = occurrence(&mut seen_chars, c);
+                                if let Some(severity) = severity
+                                    && !config.can_type(c)
+                                {
+                                    problems.push(ParseError {
+                                        kind: ParseErrorKind::UnsupportedCharacter(
+                                            c,
+                                            config.layout.name().to_string(),
+                                        ),
+                                        span: locate(
+                                            script,
+                                            action.span,
+                                            c.encode_utf8(&mut [0; 4]),
+                                            occurrence,
+                                        ),
+                                        severity,
+                                    });
+                                }
+                            }
+                        }
+                        Part::Var(name) => {
+                            let reference = format!("${{{}}}", name);
+                            let span = locate(
+                                script,
+                                action.span,
+                                &reference,
+                                occurrence(&mut seen_vars, name),
+                            );
+                            let Some(value) = config.variables.get(name) else {
+                                problems.push(ParseError::new(
+                                    ParseErrorKind::UnknownVariable(name.clone()),
+                                    span,
+                                ));
+                                continue;
+                            };
+                            let Some(severity) = severity else {
+                                continue;
+                            };
+                            let mut reported = Vec::new();
+                            for c in value.chars() {
+                                if !config.can_type(c) && !reported.contains(&c) {
+                                    reported.push(c);
+                                    problems.push(ParseError {
+                                        kind: ParseErrorKind::UnsupportedCharacter(
+                                            c,
+                                            config.layout.name().to_string(),
+                                        ),
+                                        span,
+                                        severity,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
         }
     }
 }
 
+/// Checks the body of a block `passes` times, see [`check_characters`].
+///
+/// A problem found in several passes is reported once, as an error if any
+/// pass found it to be one.
+fn check_block(
+    script: &str,
+    body: &[Spanned<Action>],
+    passes: u64,
+    config: &mut Config,
+    problems: &mut Vec<ParseError>,
+) {
+    for _ in 0..passes {
+        let mut found = Vec::new();
+        check_actions(script, body, config, &mut found);
+        for problem in found {
+            let same =
+                |other: &&mut ParseError| other.span == problem.span && other.kind == problem.kind;
+            match problems.iter_mut().find(same) {
+                Some(other) if problem.is_error() => other.severity = Severity::Error,
+                Some(_) => {}
+                None => problems.push(problem),
+            }
+        }
+    }
+}
+
+/// Parses a script and checks it for problems before it runs
+///
+/// In addition to parse errors, every reference to an undefined variable
+/// is reported, and every character that can be typed neither with the
+/// keyboard layout nor the Unicode input method in effect at that point, as
+/// an error or a warning
+/// depending on the `unsupported` policy (see [`Unsupported`]). Returns every
+/// problem found, ordered by position; the script can run if none of them
+/// [is an error](ParseError::is_error).
+///
+/// # Examples
+///
+/// ```
+/// use typewriter::config::Config;
+/// use typewriter::parser::check_script;
+///
+/// let problems = check_script("\"café\";", &Config::default());
+/// assert_eq!(problems[0].to_string(), "Cannot type 'é' with layout us");
+/// assert_eq!(problems[0].span.start, 4);
+/// ```
+pub fn check_script(script: &str, config: &Config) -> Vec<ParseError> {
+    match parse_script(script) {
+        Ok(actions) => check_characters(script, &actions, config),
+        Err(errors) => errors,
+    }
+}
+
 /// Parses and executes a typewriter script
-pub fn run_script(script: &str) -> Result<(), ParseError> {
-    let tokens = tokenize(script)?;
-    let actions = parse_actions(&tokens)?;
-    execute_actions(&actions);
-    Ok(())
+///
+/// Any keys still held down when the script finishes are released.
+///
+/// # Examples
+///
+/// ```
+/// use typewriter::backend::Recorder;
+/// use typewriter::parser::run_script;
+///
+/// let recorder = Recorder::new();
+/// recorder.record(|| run_script("\"hi\"; 500; [Ctrl, C];")).unwrap();
+/// assert_eq!(recorder.events().len(), 8);
+/// ```
+pub fn run_script(script: &str) -> Result<(), Vec<ParseError>> {
+    run_script_with_config(script, &Config::default())
+}
+
+/// Parses and executes a typewriter script starting from the given configuration
+///
+/// Settings changed by the script's `set` directives do not affect `config`.
+///
+/// # Errors
+///
+/// Returns every parse error, or every character the layout cannot type
+/// under the [`Unsupported::Error`] policy. No keys are sent in either case.
+/// If an action still fails while the script runs (see [`execute_action`]),
+/// the script stops there and that error is returned.
+pub fn run_script_with_config(script: &str, config: &Config) -> Result<(), Vec<ParseError>> {
+    let actions = parse_script(script)?;
+    let errors: Vec<ParseError> = check_characters(script, &actions, config)
+        .into_iter()
+        .filter(ParseError::is_error)
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let mut config = config.clone();
+    let result = execute_actions(&actions, &mut config);
+    release_held_keys(&config);
+    result.map_err(|err| vec![err])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Recorder;
+    use crate::editor::Editor;
+    use crate::typewriter::held_keys;
+    use rdev::EventType;
+
+    /// Parses a script that must contain only chords, returning their keys.
+    fn chords(script: &str) -> Vec<Vec<Key>> {
+        parse_script(script)
+            .unwrap()
+            .into_iter()
+            .map(|action| match action.node {
+                Action::TypeChord(keys) => keys,
+                other => panic!("Expected a chord, got {}", other),
+            })
+            .collect()
+    }
+
+    /// Tokenizes `script`, returning each token's debug form and the errors.
+    fn tokens(script: &str) -> (Vec<String>, Vec<ParseError>) {
+        let mut errors = Vec::new();
+        let tokens = tokenize(script, &mut errors);
+        let tokens = tokens.iter().map(|t| format!("{:?}", t.node)).collect();
+        (tokens, errors)
+    }
+
+    #[test]
+    fn hello_example() {
+        let script = include_str!("../examples/hello.tw");
+
+        let editor = Editor::new();
+        editor.record(|| run_script(script)).unwrap();
+        assert_eq!(
+            editor.text(),
+            "jecho 'Hello, World!'\necho 'Goodbye Moon?'\n"
+        );
+
+        let recorder = Recorder::new();
+        recorder.record(|| run_script(script)).unwrap();
+        let events = recorder.events();
+        let first: Vec<EventType> = events[..6].iter().map(|e| e.event_type).collect();
+        assert_eq!(
+            first,
+            [
+                EventType::KeyPress(Key::ControlLeft),
+                EventType::KeyPress(Key::Space),
+                EventType::KeyRelease(Key::ControlLeft),
+                EventType::KeyRelease(Key::Space),
+                EventType::KeyPress(Key::KeyJ),
+                EventType::KeyRelease(Key::KeyJ),
+            ]
+        );
+        // 102 events 20ms apart, 20 characters at 50ms and 20 at 40ms, and
+        // two 300ms pauses
+        assert_eq!(events.len(), 102);
+        assert_eq!(recorder.elapsed(), 4_440);
+    }
+
+    #[test]
+    fn tokenizes_statements() {
+        let (tokens, errors) = tokens("\"hi\"; 300ms; // pause\n[Ctrl, C]; <C-s>;");
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens,
+            [
+                "String(\"hi\")",
+                "Semicolon",
+                "Duration(300)",
+                "Semicolon",
+                "LeftBracket",
+                "Identifier(\"Ctrl\")",
+                "Comma",
+                "Identifier(\"C\")",
+                "RightBracket",
+                "Semicolon",
+                "Chord(\"C-s\")",
+                "Semicolon",
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizer_skips_stray_characters() {
+        let (tokens, errors) = tokens("\"a\"; @ \"b\";");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].span, Span { start: 5, end: 6 });
+        assert!(tokens.contains(&"String(\"b\")".to_string()));
+    }
+
+    #[test]
+    fn reports_every_error_and_keeps_parsing() {
+        let errors =
+            parse_script("[Nope]; \"ok\"; 10x; repeat 2 { [Ctrll, A]; } \"end\";").unwrap_err();
+        let messages: Vec<String> = errors.iter().map(|e| e.kind.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Unknown key: Nope",
+                "Invalid syntax: Unknown unit \"x\" (expected ms, s, m, h or wpm)",
+                "Unknown key: Ctrll (did you mean Ctrl?)",
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenizes_escapes() {
+        let (escaped, errors) = tokens(r#""a\tb\u{e9}\\";"#);
+        assert!(errors.is_empty());
+        assert_eq!(escaped[0], "String(\"a\\tbé\\\\\")");
+        let (_, errors) = tokens(r#""a\qb"; "\u{d800}"; "open"#);
+        let kinds: Vec<&ParseErrorKind> = errors.iter().map(|e| &e.kind).collect();
+        assert!(matches!(
+            kinds[..],
+            [
+                ParseErrorKind::InvalidEscape(_),
+                ParseErrorKind::InvalidEscape(_),
+                ParseErrorKind::UnterminatedString,
+            ]
+        ));
+    }
+
+    #[test]
+    fn parses_every_key_and_raw_keycodes() {
+        assert_eq!(
+            chords("[KpDelete, Function, IntlBackslash, Raw(0x2F)];"),
+            [vec![
+                Key::KpDelete,
+                Key::Function,
+                Key::IntlBackslash,
+                Key::Unknown(0x2F)
+            ]]
+        );
+    }
+
+    #[test]
+    fn matches_key_names_case_insensitively() {
+        assert_eq!(parse_key("ESC"), Ok(Key::Escape));
+        assert_eq!(parse_key("key::pageup"), Ok(Key::PageUp));
+        assert_eq!(parse_key("Cmd"), Ok(Key::MetaLeft));
+        assert_eq!(
+            parse_key("Escpe"),
+            Err(ParseErrorKind::UnknownKey(
+                "Escpe".to_string(),
+                Some("Escape".to_string())
+            ))
+        );
+        assert_eq!(chords("[Ctrl, 1];"), [vec![Key::ControlLeft, Key::Num1]]);
+    }
+
+    #[test]
+    fn parses_chord_shorthand() {
+        assert_eq!(
+            chords("<C-S-t>; Ctrl+Shift+T; <c-;>; <A-F4>;"),
+            [
+                vec![Key::ControlLeft, Key::ShiftLeft, Key::KeyT],
+                vec![Key::ControlLeft, Key::ShiftLeft, Key::KeyT],
+                vec![Key::ControlLeft, Key::SemiColon],
+                vec![Key::Alt, Key::F4],
+            ]
+        );
+        assert_eq!(chords("<C-x> <C-s>;").len(), 2);
+        assert!(parse_script("<Q-x>;").is_err());
+    }
+
+    #[test]
+    fn parses_held_keys() {
+        let actions: Vec<Action> = parse_script("down [Shift]; up [Shift]; hold [A] for 1s;")
+            .unwrap()
+            .into_iter()
+            .map(|action| action.node)
+            .collect();
+        assert!(matches!(
+            &actions[..],
+            [
+                Action::KeyDown(down),
+                Action::KeyUp(up),
+                Action::Hold(held, 1_000),
+            ] if down == &[Key::ShiftLeft] && up == &[Key::ShiftLeft] && held == &[Key::KeyA]
+        ));
+        assert!(parse_script("hold [A];").is_err());
+    }
+
+    #[test]
+    fn releases_keys_left_held_when_a_script_ends() {
+        let recorder = Recorder::new();
+        recorder.record(|| {
+            run_script("down [Shift];").unwrap();
+            assert!(held_keys().is_empty());
+        });
+        let events: Vec<EventType> = recorder.events().iter().map(|e| e.event_type).collect();
+        assert_eq!(
+            events,
+            [
+                EventType::KeyPress(Key::ShiftLeft),
+                EventType::KeyRelease(Key::ShiftLeft),
+            ]
+        );
+    }
+
+    #[test]
+    fn settings_change_the_default_delays() {
+        let elapsed = |script| {
+            let recorder = Recorder::new();
+            recorder.record(|| run_script(script)).unwrap();
+            recorder.elapsed()
+        };
+        // A press and a release, each followed by the key delay, then the
+        // typing delay
+        assert_eq!(elapsed("\"a\";"), 20 + 20 + 50);
+        assert_eq!(
+            elapsed("set typing_delay = 30; set key_delay = 5; \"a\";"),
+            5 + 5 + 30
+        );
+        assert_eq!(elapsed("set key_delay = 5; [A];"), 5 + 5);
+    }
+
+    #[test]
+    fn parses_durations() {
+        let duration = |text| match number_literal(text) {
+            Ok(Token::Duration(millis)) => millis,
+            other => panic!("Expected a duration for {}, got {:?}", text, other),
+        };
+        assert_eq!(duration("300ms"), 300);
+        assert_eq!(duration("1.5s"), 1_500);
+        assert_eq!(duration("1m30s"), 90_000);
+        assert_eq!(duration("2h"), 7_200_000);
+        assert!(matches!(number_literal("500"), Ok(Token::Number(500))));
+        assert!(matches!(number_literal("90wpm"), Ok(Token::Wpm(90))));
+        for invalid in ["0wpm", "1.5", "10x", "1m30", "1..5s"] {
+            assert!(
+                number_literal(invalid).is_err(),
+                "{} should not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn checks_blocks_with_the_settings_they_leave_behind() {
+        let script = "set unsupported = \"skip\"; repeat 2 { \"é\"; set unsupported = \"error\"; }";
+        let problems = check_script(script, &Config::default());
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].is_error());
+        assert!(
+            check_script(
+                "repeat 1 { \"é\"; set unsupported = \"error\"; }",
+                &Config {
+                    unsupported: Unsupported::Skip,
+                    ..Config::default()
+                }
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn repeats_blocks() {
+        let recorder = Recorder::new();
+        recorder
+            .record(|| run_script("repeat 3 { [A]; 100; } loop for 250ms { 100; }"))
+            .unwrap();
+        assert_eq!(recorder.events().len(), 6);
+        assert_eq!(recorder.elapsed(), 3 * (20 + 20 + 100) + 300);
+    }
+
+    #[test]
+    fn timed_loop_without_delay_ends() {
+        let recorder = Recorder::new();
+        recorder
+            .record(|| run_script("set key_delay = 0; loop for 1s { [A]; }"))
+            .unwrap();
+        assert_eq!(recorder.events().len(), 2);
+        assert_eq!(recorder.elapsed(), 0);
+    }
+
+    #[test]
+    fn rejects_loops_that_never_take_time() {
+        for script in [
+            "loop { }",
+            "loop { let x = \"y\"; }",
+            "loop { set seed = 1; \"\"; 0; }",
+            "loop for 1s { repeat 0 { [A]; } }",
+        ] {
+            assert!(parse_script(script).is_err(), "{} should not parse", script);
+        }
+        assert!(parse_script("loop { repeat 2 { [A]; } }").is_ok());
+        assert!(parse_script("loop { \"${x}\"; }").is_ok());
+    }
+
+    #[test]
+    fn tokenizes_templates() {
+        let (tokens, errors) = tokens(r#""Hi ${name}! \$5";"#);
+        assert!(errors.is_empty());
+        assert_eq!(
+            tokens[0],
+            "Template([Text(\"Hi \"), Var(\"name\"), Text(\"! $5\")])"
+        );
+    }
+
+    #[test]
+    fn interpolates_variables() {
+        let editor = Editor::new();
+        let script = "let user = \"ada\"; let greeting = \"Hi ${user}\"; \"${greeting}!\";";
+        editor.record(|| run_script(script)).unwrap();
+        assert_eq!(editor.text(), "Hi ada!");
+    }
+
+    #[test]
+    fn execution_errors_do_not_panic() {
+        let recorder = Recorder::new();
+        let actions = parse_script("\"${missing}\";").unwrap();
+        let error = recorder
+            .record(|| execute_actions(&actions, &mut Config::default()))
+            .unwrap_err();
+        assert!(matches!(error.kind, ParseErrorKind::UnknownVariable(_)));
+        assert!(recorder.events().is_empty());
+    }
 }
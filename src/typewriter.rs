@@ -2,26 +2,67 @@
 //!
 //! This module provides functions to simulate keyboard input, including
 //! individual characters, strings, and key chords (multiple keys pressed
-//! simultaneously). It uses the `rdev` library to send keyboard events
-//! to the operating system.
+//! simultaneously). Keyboard events are dispatched through the active
+//! [`Backend`](crate::backend::Backend), which by default uses the `rdev`
+//! library to send them to the operating system.
 //!
 //! # Examples
 //!
-//! ```
-//! use typewriter::*;
+//! ```no_run
+//! use rdev::Key;
+//! use typewriter::config::Config;
+//! use typewriter::typewriter::*;
 //!
 //! // Type a simple string
-//! type_string("Hello, World!".to_string(), 50);
+//! type_string("Hello, World!".to_string(), 50, &Config::default()).unwrap();
 //!
 //! // Type a keyboard shortcut (Ctrl+C)
-//! type_chord(vec![Key::ControlLeft, Key::KeyC]);
+//! type_chord(vec![Key::ControlLeft, Key::KeyC], &Config::default());
 //! ```
 
-use rdev::{EventType, Key, SimulateError, simulate};
-use std::{thread, time};
+use crate::backend::with_backend;
+use crate::config::{CarriageReturn, Config, Indent, UnicodeInput, Unsupported};
+use crate::layout::KEYPAD_DIGITS;
+use rdev::{EventType, Key, SimulateError};
+use std::error::Error;
+use std::fmt;
+
+/// A character that neither the keyboard layout nor the Unicode input method
+/// can type, under the [`Unsupported::Error`] policy.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedCharacter {
+    /// The character that could not be typed.
+    pub character: char,
+    /// The name of the layout in effect.
+    pub layout: String,
+}
+
+impl UnsupportedCharacter {
+    fn new(character: char, config: &Config) -> Self {
+        Self {
+            character,
+            layout: config.layout.name().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for UnsupportedCharacter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Cannot type {:?} with layout {}",
+            self.character, self.layout
+        )
+    }
+}
+
+impl Error for UnsupportedCharacter {}
 
 /// Pauses execution for the specified number of milliseconds.
 ///
+/// The pause is delegated to the active backend, so recording backends can
+/// advance a virtual clock instead of blocking.
+///
 /// # Arguments
 ///
 /// * `millis` - The number of milliseconds to sleep
@@ -29,219 +70,456 @@ use std::{thread, time};
 /// # Examples
 ///
 /// ```
+/// # use typewriter::typewriter::sleep;
 /// sleep(100); // Pauses for 100 milliseconds
 /// ```
 pub fn sleep(millis: u64) {
-    thread::sleep(time::Duration::from_millis(millis));
+    with_backend(|backend| backend.sleep(millis));
 }
 
-/// Sends a keyboard event and waits 50ms.
+/// Returns the current time in milliseconds according to the active backend.
 ///
-/// This is an internal helper function that simulates a keyboard event
-/// (such as a key press or release) and includes a small delay to ensure
-/// proper event processing.
+/// Only differences between two readings are meaningful. Recording backends
+/// report their virtual clock.
+pub fn now() -> u64 {
+    with_backend(|backend| backend.now())
+}
+
+/// Sends a keyboard event and waits `config.key_delay` milliseconds.
+///
+/// This is an internal helper function that dispatches a keyboard event
+/// (such as a key press or release) to the active backend and includes a
+/// small delay to ensure proper event processing.
 //
 /// # Arguments
 ///
 /// * `event_type` - The keyboard event to simulate (KeyPress or KeyRelease)
+/// * `config` - The configuration providing the delay after the event
 ///
 /// # Errors
 ///
 /// Prints an error message to stdout if the event simulation fails.
-fn send(event_type: &EventType) {
-    match simulate(event_type) {
+fn send(event_type: &EventType, config: &Config) {
+    match with_backend(|backend| backend.send(event_type)) {
         Ok(()) => (),
         Err(SimulateError) => {
             println!("We could not send {:?}", event_type);
         }
     }
     // Sleep enough time to ensure proper event processing.
-    sleep(20)
+    sleep(config.key_delay)
+}
+
+/// Returns the keys that are currently held down through the active
+/// backend, in press order.
+pub fn held_keys() -> Vec<Key> {
+    with_backend(|backend| backend.held_keys())
+}
+
+/// Presses keys without releasing them.
+///
+/// The keys stay held across subsequent calls until they are released with
+/// [`release_chord`], which allows holding a modifier while typing.
+///
+/// # Arguments
+///
+/// * `keys` - The keys to press, in order
+/// * `config` - The configuration providing the delay after each key
+///
+/// # Examples
+///
+/// ```no_run
+/// # use rdev::Key;
+/// # use typewriter::config::Config;
+/// # use typewriter::typewriter::{press_chord, release_chord, type_chord};
+/// // Alt+Tab twice while keeping Alt held
+/// let config = Config::default();
+/// press_chord(vec![Key::Alt], &config);
+/// type_chord(vec![Key::Tab], &config);
+/// type_chord(vec![Key::Tab], &config);
+/// release_chord(vec![Key::Alt], &config);
+/// ```
+pub fn press_chord(keys: Vec<Key>, config: &Config) {
+    for key in &keys {
+        send(&EventType::KeyPress(*key), config);
+    }
+}
+
+/// Releases keys previously pressed with [`press_chord`].
+///
+/// # Arguments
+///
+/// * `keys` - The keys to release, in order
+/// * `config` - The configuration providing the delay after each key
+pub fn release_chord(keys: Vec<Key>, config: &Config) {
+    for key in &keys {
+        send(&EventType::KeyRelease(*key), config);
+    }
+}
+
+/// Presses keys, holds them for the given duration, then releases them.
+///
+/// Holding a key long enough triggers the operating system's auto-repeat.
+///
+/// # Arguments
+///
+/// * `keys` - The keys to hold
+/// * `millis` - How long to hold them, in milliseconds
+/// * `config` - The configuration providing the delay after each key
+pub fn hold_chord(keys: Vec<Key>, millis: u64, config: &Config) {
+    press_chord(keys.clone(), config);
+    sleep(millis);
+    release_chord(keys, config);
+}
+
+/// Releases every key that is still held down through the active backend.
+pub fn release_held_keys(config: &Config) {
+    release_chord(held_keys(), config);
 }
 
 /// Types a chord by pressing multiple keys simultaneously.
 ///
 /// This function simulates pressing multiple keys at once (like keyboard shortcuts).
 /// All keys are pressed in sequence, then all keys are released in the same order.
+/// Keys that are already held down (see [`press_chord`]) are left untouched, so
+/// they remain held after the chord.
 ///
 /// # Arguments
 ///
 /// * `keys` - A vector of keys to press together
+/// * `config` - The configuration providing the delay after each key
 ///
 /// # Examples
 ///
-/// ```
+/// ```no_run
+/// # use rdev::Key;
+/// # use typewriter::config::Config;
+/// # use typewriter::typewriter::type_chord;
+/// let config = Config::default();
+///
 /// // Type Ctrl+C
-/// type_chord(vec![Key::ControlLeft, Key::KeyC]);
+/// type_chord(vec![Key::ControlLeft, Key::KeyC], &config);
 ///
 /// // Type Shift+A (capital A)
-/// type_chord(vec![Key::ShiftLeft, Key::KeyA]);
+/// type_chord(vec![Key::ShiftLeft, Key::KeyA], &config);
 /// ```
-pub fn type_chord(keys: Vec<Key>) {
+pub fn type_chord(keys: Vec<Key>, config: &Config) {
+    let held = held_keys();
+    let keys: Vec<Key> = keys.into_iter().filter(|key| !held.contains(key)).collect();
     // press
     for key in &keys {
-        send(&EventType::KeyPress(*key));
+        send(&EventType::KeyPress(*key), config);
     }
     // release
     for key in &keys {
-        send(&EventType::KeyRelease(*key));
+        send(&EventType::KeyRelease(*key), config);
     }
 }
 
 /// Types a single character by simulating the appropriate key combination.
 ///
-/// Supports lowercase letters, uppercase letters, digits, and common symbols.
-/// For uppercase letters and symbols requiring Shift, the function automatically
-/// includes the Shift key in the chord.
+/// The keys to press are looked up in the configured keyboard layout, so the
+/// same character is typed correctly regardless of the keymap the operating
+/// system is using. For uppercase letters and symbols requiring Shift or
+/// AltGr, the function automatically includes the modifier in the chord.
 ///
 /// # Arguments
 ///
 /// * `c` - The character to type
-///
-/// # Supported Characters
-///
-/// - Lowercase letters (a-z)
-/// - Uppercase letters (A-Z)
-/// - Digits (0-9)
-/// - Symbols: `! @ # $ % ^ & * ( ) - _ = + [ ] { } \ | ; : ' " , < . > / ? ` ~`
-/// - Space
+/// * `config` - The configuration providing the keyboard layout
 ///
 /// # Examples
 ///
+/// ```no_run
+/// # use typewriter::config::Config;
+/// # use typewriter::typewriter::type_char;
+/// let config = Config::default();
+/// type_char('a', &config).unwrap();  // Types lowercase 'a'
+/// type_char('A', &config).unwrap();  // Types uppercase 'A' (with Shift)
+/// type_char('!', &config).unwrap();  // Types '!' (Shift+1)
 /// ```
-/// type_char('a');  // Types lowercase 'a'
-/// type_char('A');  // Types uppercase 'A' (with Shift)
-/// type_char('!');  // Types '!' (Shift+1)
-/// ```
 ///
-/// # Note
-///
-/// Unsupported characters are silently ignored.
-pub fn type_char(c: char) {
-    match c {
-        'a' => type_chord(vec![Key::KeyA]),
-        'b' => type_chord(vec![Key::KeyB]),
-        'c' => type_chord(vec![Key::KeyC]),
-        'd' => type_chord(vec![Key::KeyD]),
-        'e' => type_chord(vec![Key::KeyE]),
-        'f' => type_chord(vec![Key::KeyF]),
-        'g' => type_chord(vec![Key::KeyG]),
-        'h' => type_chord(vec![Key::KeyH]),
-        'i' => type_chord(vec![Key::KeyI]),
-        'j' => type_chord(vec![Key::KeyJ]),
-        'k' => type_chord(vec![Key::KeyK]),
-        'l' => type_chord(vec![Key::KeyL]),
-        'm' => type_chord(vec![Key::KeyM]),
-        'n' => type_chord(vec![Key::KeyN]),
-        'o' => type_chord(vec![Key::KeyO]),
-        'p' => type_chord(vec![Key::KeyP]),
-        'q' => type_chord(vec![Key::KeyQ]),
-        'r' => type_chord(vec![Key::KeyR]),
-        's' => type_chord(vec![Key::KeyS]),
-        't' => type_chord(vec![Key::KeyT]),
-        'u' => type_chord(vec![Key::KeyU]),
-        'v' => type_chord(vec![Key::KeyV]),
-        'w' => type_chord(vec![Key::KeyW]),
-        'x' => type_chord(vec![Key::KeyX]),
-        'y' => type_chord(vec![Key::KeyY]),
-        'z' => type_chord(vec![Key::KeyZ]),
-        'A' => type_chord(vec![Key::ShiftLeft, Key::KeyA]),
-        'B' => type_chord(vec![Key::ShiftLeft, Key::KeyB]),
-        'C' => type_chord(vec![Key::ShiftLeft, Key::KeyC]),
-        'D' => type_chord(vec![Key::ShiftLeft, Key::KeyD]),
-        'E' => type_chord(vec![Key::ShiftLeft, Key::KeyE]),
-        'F' => type_chord(vec![Key::ShiftLeft, Key::KeyF]),
-        'G' => type_chord(vec![Key::ShiftLeft, Key::KeyG]),
-        'H' => type_chord(vec![Key::ShiftLeft, Key::KeyH]),
-        'I' => type_chord(vec![Key::ShiftLeft, Key::KeyI]),
-        'J' => type_chord(vec![Key::ShiftLeft, Key::KeyJ]),
-        'K' => type_chord(vec![Key::ShiftLeft, Key::KeyK]),
-        'L' => type_chord(vec![Key::ShiftLeft, Key::KeyL]),
-        'M' => type_chord(vec![Key::ShiftLeft, Key::KeyM]),
-        'N' => type_chord(vec![Key::ShiftLeft, Key::KeyN]),
-        'O' => type_chord(vec![Key::ShiftLeft, Key::KeyO]),
-        'P' => type_chord(vec![Key::ShiftLeft, Key::KeyP]),
-        'Q' => type_chord(vec![Key::ShiftLeft, Key::KeyQ]),
-        'R' => type_chord(vec![Key::ShiftLeft, Key::KeyR]),
-        'S' => type_chord(vec![Key::ShiftLeft, Key::KeyS]),
-        'T' => type_chord(vec![Key::ShiftLeft, Key::KeyT]),
-        'U' => type_chord(vec![Key::ShiftLeft, Key::KeyU]),
-        'V' => type_chord(vec![Key::ShiftLeft, Key::KeyV]),
-        'W' => type_chord(vec![Key::ShiftLeft, Key::KeyW]),
-        'X' => type_chord(vec![Key::ShiftLeft, Key::KeyX]),
-        'Y' => type_chord(vec![Key::ShiftLeft, Key::KeyY]),
-        'Z' => type_chord(vec![Key::ShiftLeft, Key::KeyZ]),
-        '1' => type_chord(vec![Key::Num1]),
-        '2' => type_chord(vec![Key::Num2]),
-        '3' => type_chord(vec![Key::Num3]),
-        '4' => type_chord(vec![Key::Num4]),
-        '5' => type_chord(vec![Key::Num5]),
-        '6' => type_chord(vec![Key::Num6]),
-        '7' => type_chord(vec![Key::Num7]),
-        '8' => type_chord(vec![Key::Num8]),
-        '9' => type_chord(vec![Key::Num9]),
-        '0' => type_chord(vec![Key::Num0]),
-        '!' => type_chord(vec![Key::ShiftLeft, Key::Num1]),
-        '@' => type_chord(vec![Key::ShiftLeft, Key::Num2]),
-        '#' => type_chord(vec![Key::ShiftLeft, Key::Num3]),
-        '$' => type_chord(vec![Key::ShiftLeft, Key::Num4]),
-        '%' => type_chord(vec![Key::ShiftLeft, Key::Num5]),
-        '^' => type_chord(vec![Key::ShiftLeft, Key::Num6]),
-        '&' => type_chord(vec![Key::ShiftLeft, Key::Num7]),
-        '*' => type_chord(vec![Key::ShiftLeft, Key::Num8]),
-        '(' => type_chord(vec![Key::ShiftLeft, Key::Num9]),
-        ')' => type_chord(vec![Key::ShiftLeft, Key::Num0]),
-        ' ' => type_chord(vec![Key::Space]),
-        ',' => type_chord(vec![Key::Comma]),
-        '<' => type_chord(vec![Key::ShiftLeft, Key::Comma]),
-        '.' => type_chord(vec![Key::Dot]),
-        '>' => type_chord(vec![Key::ShiftLeft, Key::Dot]),
-        '/' => type_chord(vec![Key::Slash]),
-        '?' => type_chord(vec![Key::ShiftLeft, Key::Slash]),
-        ';' => type_chord(vec![Key::SemiColon]),
-        ':' => type_chord(vec![Key::ShiftLeft, Key::SemiColon]),
-        '\'' => type_chord(vec![Key::Quote]),
-        '"' => type_chord(vec![Key::ShiftLeft, Key::Quote]),
-        '[' => type_chord(vec![Key::LeftBracket]),
-        '{' => type_chord(vec![Key::ShiftLeft, Key::LeftBracket]),
-        ']' => type_chord(vec![Key::RightBracket]),
-        '}' => type_chord(vec![Key::ShiftLeft, Key::RightBracket]),
-        '\\' => type_chord(vec![Key::BackSlash]),
-        '|' => type_chord(vec![Key::ShiftLeft, Key::BackSlash]),
-        '-' => type_chord(vec![Key::Minus]),
-        '_' => type_chord(vec![Key::ShiftLeft, Key::Minus]),
-        '=' => type_chord(vec![Key::Equal]),
-        '+' => type_chord(vec![Key::ShiftLeft, Key::Equal]),
-        '`' => type_chord(vec![Key::BackQuote]),
-        '~' => type_chord(vec![Key::ShiftLeft, Key::BackQuote]),
-        _ => {}
+/// Newlines (`\n`) are typed with `config.newline`, tabs (`\t`) with Tab, and
+/// carriage returns (`\r`) are ignored or typed as newlines depending on
+/// `config.carriage_return`.
+///
+/// Characters that are not on the layout are entered by their code point
+/// if `config.unicode` selects an input method that can type them.
+///
+/// Other policies than [`Unsupported::Error`] skip characters that cannot
+/// be typed, optionally with a warning, or type a substitute for them.
+///
+/// # Errors
+///
+/// Returns [`UnsupportedCharacter`] without typing anything if `c` cannot be
+/// typed and `config.unsupported` is [`Unsupported::Error`]. Scripts are
+/// checked for such characters before they run, see
+/// [`crate::parser::check_script`].
+pub fn type_char(c: char, config: &Config) -> Result<(), UnsupportedCharacter> {
+    let c = match (c, config.carriage_return) {
+        ('\r', CarriageReturn::Ignore) => return Ok(()),
+        ('\r', CarriageReturn::Newline) => '\n',
+        _ => c,
+    };
+    if c == '\n' {
+        type_chord(config.newline.chord(), config);
+        return Ok(());
+    }
+    if type_on_layout(c, config) {
+        return Ok(());
+    }
+    if config.unicode.can_type(c, &config.layout) {
+        type_unicode(c, config);
+    } else {
+        match config.unsupported {
+            Unsupported::Error => return Err(UnsupportedCharacter::new(c, config)),
+            Unsupported::Warn => eprintln!(
+                "Warning: cannot type {:?} with layout {}, skipping it",
+                c,
+                config.layout.name()
+            ),
+            Unsupported::Skip => {}
+            Unsupported::Fallback => {
+                for substitute in config.layout.fallback(c).unwrap_or_default().chars() {
+                    type_char(substitute, config)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Types the chords that produce `c` on the configured layout, returning
+/// false if there are none.
+fn type_on_layout(c: char, config: &Config) -> bool {
+    let Some(chords) = config.layout.chord(c) else {
+        return false;
+    };
+    for chord in chords {
+        type_chord(chord.clone(), config);
+    }
+    true
+}
+
+/// Enters `c` by its hex code point through the configured input method.
+///
+/// The caller must check that the input method can type `c` with the layout.
+fn type_unicode(c: char, config: &Config) {
+    let layout = &config.layout;
+    match config.unicode {
+        UnicodeInput::None => {}
+        UnicodeInput::Ibus => {
+            // The chord for lowercase "u" ends in the physical key that produces it
+            if let Some(u) = layout.chord('u').and_then(|chords| chords[0].last()) {
+                type_chord(vec![Key::ControlLeft, Key::ShiftLeft, *u], config);
+            }
+            for digit in format!("{:x}", c as u32).chars() {
+                type_on_layout(digit, config);
+            }
+            type_chord(vec![Key::Space], config);
+        }
+        UnicodeInput::MacOs => {
+            press_chord(vec![Key::Alt], config);
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                for digit in format!("{:04x}", unit).chars() {
+                    type_on_layout(digit, config);
+                }
+            }
+            release_chord(vec![Key::Alt], config);
+        }
+        UnicodeInput::Windows => {
+            press_chord(vec![Key::Alt], config);
+            type_chord(vec![Key::KpPlus], config);
+            for digit in format!("{:x}", c as u32).chars() {
+                match KEYPAD_DIGITS.get(digit.to_digit(10).unwrap_or(10) as usize) {
+                    Some(key) => type_chord(vec![*key], config),
+                    None => {
+                        type_on_layout(digit, config);
+                    }
+                }
+            }
+            release_chord(vec![Key::Alt], config);
+        }
     }
 }
 
 /// Types an entire string by simulating keystrokes for each character.
 ///
 /// This function iterates through each character in the string and calls
-/// `type_char` to simulate the appropriate key combination. Characters that
-/// are not supported by `type_char` will be silently skipped.
+/// `type_char` to simulate the appropriate key combination. Characters the
+/// layout cannot produce are handled according to `config.unsupported`, and
+/// indentation after newlines according to `config.indent`. The delay after
+/// each character varies according to `config.humanize`, and characters are
+/// occasionally mistyped and corrected according to `config.typos`.
 ///
 /// # Arguments
 ///
 /// * `s` - The string to type
 /// * `sleep_millis` - The number of milliseconds to wait between typing each character
+/// * `config` - The configuration providing the keyboard layout
 ///
 /// # Examples
 ///
-/// ```
-/// type_string("Hello, World!".to_string(), 50);
-/// type_string("user@example.com".to_string(), 100);
+/// ```no_run
+/// # use typewriter::config::Config;
+/// # use typewriter::typewriter::type_string;
+/// let config = Config::default();
+/// type_string("Hello, World!".to_string(), 50, &config).unwrap();
+/// type_string("user@example.com".to_string(), 100, &config).unwrap();
 /// ```
 ///
 /// # See Also
 ///
 /// - [`type_char`] for details on supported characters
-pub fn type_string(s: String, sleep_millis: u64) {
-    for char in s.chars() {
-        type_char(char);
-        sleep(sleep_millis)
+///
+/// # Errors
+///
+/// Returns [`UnsupportedCharacter`] for the first character that cannot be
+/// typed under the [`Unsupported::Error`] policy. The string is checked
+/// before any keys are sent, so nothing is typed in that case.
+pub fn type_string(
+    s: String,
+    sleep_millis: u64,
+    config: &Config,
+) -> Result<(), UnsupportedCharacter> {
+    if config.unsupported == Unsupported::Error
+        && let Some(c) = s.chars().find(|c| !config.can_type(*c))
+    {
+        return Err(UnsupportedCharacter::new(c, config));
+    }
+
+    let pause_after = |c: char| sleep(config.humanize.delay(c, sleep_millis, &config.rng));
+    let type_text = |text: &str| {
+        for char in text.chars() {
+            if let Some(typo) = config.typos.typo(char, &config.layout, &config.rng) {
+                type_char(typo, config)?;
+                pause_after(typo);
+                sleep(config.typos.pause);
+                type_chord(vec![Key::Backspace], config);
+                pause_after('\u{8}');
+            }
+            type_char(char, config)?;
+            pause_after(char)
+        }
+        Ok(())
+    };
+    if config.indent == Indent::Keep {
+        return type_text(&s);
+    }
+
+    // The indentation the editor inserts after a newline, assumed to be that
+    // of the last line with any content
+    let mut editor_indent = "";
+    for (i, line) in s.split('\n').enumerate() {
+        let text = line.trim_start_matches([' ', '\t']);
+        let indent = &line[..line.len() - text.len()];
+        let blank = text.trim_end_matches('\r').is_empty();
+        if i == 0 {
+            type_text(line)?;
+        } else {
+            type_text("\n")?;
+            if config.indent == Indent::Relative && !blank {
+                let common = editor_indent
+                    .bytes()
+                    .zip(indent.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                for _ in common..editor_indent.len() {
+                    type_chord(vec![Key::Backspace], config);
+                    pause_after('\u{8}')
+                }
+                type_text(&indent[common..])?;
+            }
+            type_text(text)?;
+        }
+        if !blank {
+            editor_indent = indent;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Recorder;
+    use crate::editor::tests::typed;
+
+    #[test]
+    fn chords_leave_held_keys_alone() {
+        let recorder = Recorder::new();
+        let config = Config::default();
+        recorder.record(|| {
+            press_chord(vec![Key::ShiftLeft], &config);
+            type_chord(vec![Key::ShiftLeft, Key::KeyA], &config);
+            assert_eq!(held_keys(), [Key::ShiftLeft]);
+            release_held_keys(&config);
+            assert!(held_keys().is_empty());
+        });
+        let events: Vec<EventType> = recorder.events().iter().map(|e| e.event_type).collect();
+        assert_eq!(
+            events,
+            [
+                EventType::KeyPress(Key::ShiftLeft),
+                EventType::KeyPress(Key::KeyA),
+                EventType::KeyRelease(Key::KeyA),
+                EventType::KeyRelease(Key::ShiftLeft),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_characters_fail_before_typing() {
+        let recorder = Recorder::new();
+        let error = recorder
+            .record(|| type_string("café".to_string(), 0, &Config::default()))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Cannot type 'é' with layout us");
+        assert!(recorder.events().is_empty());
+    }
+
+    #[test]
+    fn applies_the_unsupported_policy() {
+        let config = |unsupported| Config {
+            unsupported,
+            ..Config::default()
+        };
+        let skip = config(Unsupported::Skip);
+        assert_eq!(typed(|| type_string("café…".to_string(), 0, &skip)), "caf");
+        let fallback = config(Unsupported::Fallback);
+        assert_eq!(
+            typed(|| type_string("café…".to_string(), 0, &fallback)),
+            "cafe..."
+        );
+    }
+
+    #[test]
+    fn types_line_endings_and_tabs() {
+        let config = |carriage_return| Config {
+            carriage_return,
+            ..Config::default()
+        };
+        let ignore = config(CarriageReturn::Ignore);
+        assert_eq!(
+            typed(|| type_string("a\r\n\tb".to_string(), 0, &ignore)),
+            "a\n\tb"
+        );
+        let newline = config(CarriageReturn::Newline);
+        assert_eq!(
+            typed(|| type_string("a\rb".to_string(), 0, &newline)),
+            "a\nb"
+        );
+    }
+
+    #[test]
+    fn strips_indentation_after_newlines() {
+        let config = Config {
+            indent: Indent::Strip,
+            ..Config::default()
+        };
+        assert_eq!(
+            typed(|| type_string("a\n    b".to_string(), 0, &config)),
+            "a\nb"
+        );
     }
 }